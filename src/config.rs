@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::sim::{Simulation, G, RESTITUTION_COEFFICIENT, SOFTENING};
+use crate::{MAX_ZOOM, MIN_ZOOM};
+
+const CONFIG_FILE: &str = "planets.toml";
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub g: f32,
+    pub restitution: f32,
+    pub softening: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    pub window_width: i32,
+    pub window_height: i32,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            g: G,
+            restitution: RESTITUTION_COEFFICIENT,
+            softening: SOFTENING,
+            min_zoom: MIN_ZOOM,
+            max_zoom: MAX_ZOOM,
+            window_width: 800,
+            window_height: 600,
+        }
+    }
+
+    pub fn load() -> Self {
+        let path = config_path();
+
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                println!("Loaded config from {}", path.display());
+                Config::parse(&text)
+            }
+            Err(_) => {
+                println!("No config at {}, using defaults", path.display());
+                Config::new()
+            }
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut config = Config::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let result = match line.split_once('=') {
+                Some((key, value)) => config.set(key.trim(), value.trim()),
+                None => Err("expected 'key = value'".to_owned()),
+            };
+
+            if let Err(error) = result {
+                eprintln!("Warning: {} line {}: {}", CONFIG_FILE, number + 1, error);
+            }
+        }
+
+        if config.min_zoom > config.max_zoom {
+            eprintln!(
+                "Warning: {}: min_zoom {} exceeds max_zoom {}, using the default zoom range",
+                CONFIG_FILE, config.min_zoom, config.max_zoom
+            );
+            config.min_zoom = MIN_ZOOM;
+            config.max_zoom = MAX_ZOOM;
+        }
+
+        config
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = || {
+            value
+                .parse::<f32>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .ok_or(format!("invalid number '{}' for \"{}\"", value, key))
+        };
+        let positive = || {
+            number().and_then(|number| {
+                if number > 0.0 {
+                    Ok(number)
+                } else {
+                    Err(format!("\"{}\" must be positive, got {}", key, value))
+                }
+            })
+        };
+
+        match key {
+            "g" => self.g = number()?,
+            "restitution" => self.restitution = number()?,
+            "softening" => self.softening = number()?,
+            "min_zoom" => self.min_zoom = positive()?,
+            "max_zoom" => self.max_zoom = positive()?,
+            "window_width" => self.window_width = positive()?.max(1.0) as i32,
+            "window_height" => self.window_height = positive()?.max(1.0) as i32,
+            _ => return Err(format!("unknown key \"{}\"", key)),
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, simulation: &mut Simulation) {
+        simulation.g = self.g;
+        simulation.restitution = self.restitution;
+        simulation.softening = self.softening;
+    }
+}
+
+fn config_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(CONFIG_FILE)))
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_and_ignores_comments() {
+        let config = Config::parse("[physics]\ng = 100 # strong\nwindow_width = 1024\n");
+
+        assert_eq!(config.g, 100.0);
+        assert_eq!(config.window_width, 1024);
+        assert_eq!(config.restitution, RESTITUTION_COEFFICIENT);
+    }
+
+    #[test]
+    fn invalid_values_keep_defaults() {
+        let config = Config::parse(
+            "g = NaN\nsoftening = inf\nmin_zoom = 0\nwindow_width = 0\nwindow_height = -5\n",
+        );
+
+        assert_eq!(config.g, G);
+        assert_eq!(config.softening, SOFTENING);
+        assert_eq!(config.min_zoom, MIN_ZOOM);
+        assert_eq!(config.window_width, 800);
+        assert_eq!(config.window_height, 600);
+    }
+
+    #[test]
+    fn inverted_zoom_range_falls_back_to_default() {
+        let config = Config::parse("min_zoom = 5\nmax_zoom = 2\n");
+
+        assert_eq!(config.min_zoom, MIN_ZOOM);
+        assert_eq!(config.max_zoom, MAX_ZOOM);
+        assert!(config.min_zoom <= config.max_zoom);
+    }
+}
@@ -1,89 +1,371 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Instant;
+
 use macroquad::prelude::*;
 use macroquad::ui::{self, hash, widgets};
 
-const G: f32 = 0.1;
-const RESTITUTION_COEFFICIENT: f32 = 0.3;
-const ZOOM_SPEED: Vec2 = vec2(0.1, 0.1);
-const MIN_ZOOM: Vec2 = vec2(0.1, 0.1);
-const MAX_ZOOM: Vec2 = vec2(1.0, 1.0);
+mod config;
+mod precise;
+mod presets;
+mod quadtree;
+mod rng;
+mod scene;
+mod sim;
+mod sound;
+mod spatial_hash;
+mod undo;
 
-#[derive(Clone)]
-struct Planet {
-    position: Vec2,
-    radius: f32,
-    velocity: Vec2,
-    mass: f32,
-    history: Vec<Vec2>,
-    color: Color,
-}
+use config::Config;
+use rng::Rng;
+use scene::{Recording, StateScale};
+use sim::{
+    add_moon, angular_momentum, center_of_mass, circular_orbit_velocity, dominant_body,
+    hill_radius, orbital_elements, predicted_impacts, total_energy, total_momentum,
+    unbound_planets, Boundary, CollisionEvent, CollisionOutcome, Integrator, Planet, Simulation,
+    StepReport, DT, G,
+};
+use sound::Sounds;
+use undo::{snapshot_of, UndoStack};
+
+const MAX_FRAME_TIME: f32 = 0.25;
+const MIN_TIME_SCALE: f32 = 0.1;
+const MAX_TIME_SCALE: f32 = 10.0;
+const MAX_SUBSTEPS: usize = 64;
+const MAX_G: f32 = 3000.0;
+const ACCURACY: f32 = 0.05;
+const TRAIL_OPACITY: f32 = 0.2;
+const VELOCITY_SCALE: f32 = 1.0;
+const ACCELERATION_SCALE: f32 = 0.1;
+const ARROW_HEAD_SIZE: f32 = 4.0;
+const MARKER_SIZE: f32 = 6.0;
+const SLINGSHOT_SCALE: f32 = 0.5;
+const ZOOM_SPEED: f32 = 0.1;
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 20.0;
+const PAN_SPEED: f32 = 400.0;
+const STEP_REPEAT_DELAY: f32 = 0.4;
+const POLYLINE_CHUNK_SEGMENTS: usize = 800;
+const CLASSIFY_INTERVAL: usize = 30;
+const UNBOUND_COLOR: Color = Color::new(0.6, 0.6, 0.6, 0.6);
+const DENSITY_CONSTANT: f32 = 2.0;
+const CLEAR_CONFIRM_TIME: f64 = 2.0;
+const DUPLICATE_OFFSET: f32 = 5.0;
+const MOON_RADIUS: f32 = 2.0;
+const FIT_MARGIN: f32 = 1.2;
+const SELECTION_GAP: f32 = 3.0;
+const SELECTION_COLOR: Color = Color::new(0.3, 0.8, 1.0, 0.9);
+const SELECTION_FILL: Color = Color::new(0.3, 0.8, 1.0, 0.15);
+const STAR_SEED: u64 = 7;
+const STAR_TILE: f32 = 1024.0;
+const STAR_COUNT: usize = 400;
+const GRID_PIXELS: f32 = 80.0;
+const FIELD_CELLS: usize = 48;
+const LABEL_SIZE: u16 = 16;
+const LABEL_OFFSET: f32 = 4.0;
+const ORBIT_SEGMENTS: usize = 128;
+const HELP_LINE_HEIGHT: f32 = 22.0;
+const HELP_PADDING: f32 = 16.0;
+const MINIMAP_SIZE: Vec2 = vec2(160.0, 120.0);
+const MINIMAP_MARGIN: f32 = 10.0;
+const HISTOGRAM_BINS: usize = 20;
+const HISTOGRAM_SIZE: Vec2 = vec2(260.0, 80.0);
+const ENERGY_SAMPLES: usize = 300;
+const MAX_COLLISIONS: usize = 100;
+const GRAPH_SIZE: Vec2 = vec2(260.0, 80.0);
+const IMPACT_HORIZON: f32 = 3.0;
+const IMPACT_MAX_BODIES: usize = 500;
+const IMPACT_COLOR: Color = Color::new(1.0, 0.4, 0.2, 0.9);
+const BENCH_SIZE: Vec2 = vec2(800.0, 600.0);
 
 impl Planet {
-    fn new(position: Vec2, radius: f32, velocity: Vec2, mass: f32, color: Color) -> Self {
-        Planet {
-            position,
-            radius,
-            velocity,
-            mass,
-            history: Vec::new(),
-            color,
-        }
+    fn drawn_center(&self) -> Vec2 {
+        self.position
     }
 
-    fn update(&mut self, other_planets: &mut [Planet]) {
-        let mut acceleration = Vec2::ZERO;
+    fn draw(&self, trail: &[Vec2], color: Color, break_distance: f32, view_params: &ViewParams) {
+        if self.show_trail {
+            let speeds: Vec<f32> = trail
+                .windows(2)
+                .map(|pair| pair[0].distance(pair[1]))
+                .collect();
+            let colors = trail_colors(&speeds, trail.len(), color, break_distance, view_params);
+
+            let mut start = 0;
 
-        for i in (0..other_planets.len()).rev() {
-            let other_planet = &mut other_planets[i];
-            if self.position != other_planet.position {
-                let direction = other_planet.position - self.position;
-                let distance_squared = direction.length_squared();
-                let force_magnitude = G * ((other_planet.mass * self.mass) / distance_squared);
+            for end in 1..=trail.len() {
+                if end == trail.len() || speeds[end - 1] > break_distance {
+                    draw_polyline(&trail[start..end], 1.0, |i| colors[start + i]);
+                    start = end;
+                }
+            }
+        }
 
-                acceleration += direction.normalize() * force_magnitude;
+        let center = self.drawn_center();
 
-                if distance_squared <= (self.radius + other_planet.radius).powi(2) {
-                    let collision_normal = direction.normalize();
-                    let relative_velocity = self.velocity - other_planet.velocity;
-                    let impulse = (2.0 * self.mass * other_planet.mass)
-                        / (self.mass + other_planet.mass)
-                        * relative_velocity.dot(collision_normal);
-                    let impulse = impulse * RESTITUTION_COEFFICIENT;
+        if self.is_test_particle() {
+            draw_rectangle(center.x - 0.5, center.y - 0.5, 1.0, 1.0, color);
+        } else {
+            draw_circle(center.x, center.y, self.radius, color);
 
-                    self.velocity -= impulse * collision_normal;
-                    other_planet.velocity += impulse * collision_normal;
-                }
+            if view_params.light_background() {
+                draw_circle_lines(
+                    center.x,
+                    center.y,
+                    self.radius,
+                    1.0 / view_params.zoom,
+                    view_params.foreground(0.5),
+                );
             }
         }
+    }
+
+    fn draw_label(&self, index: usize, view_params: &ViewParams) {
+        let mut parts = Vec::new();
+
+        if view_params.label_name {
+            parts.push(self.display_name(index));
+        }
+
+        if view_params.label_mass {
+            parts.push(format!("m={:.1}", self.mass));
+        }
+
+        if view_params.label_speed {
+            parts.push(format!("v={:.1}", self.velocity.length()));
+        }
+
+        if parts.is_empty() {
+            return;
+        }
+
+        let scale = 1.0 / view_params.zoom;
+
+        draw_text_ex(
+            &parts.join(" "),
+            self.position.x + self.radius + LABEL_OFFSET * scale,
+            self.position.y - self.radius,
+            TextParams {
+                font_size: LABEL_SIZE,
+                font_scale: scale,
+                color: view_params.foreground(0.8),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn draw_velocity(&self, frame_velocity: Vec2, scale: f32) {
+        draw_arrow(
+            self.position,
+            self.position + (self.velocity - frame_velocity) * scale,
+            YELLOW,
+        );
+    }
+
+    fn draw_acceleration(&self, frame_acceleration: Vec2, scale: f32) {
+        draw_arrow(
+            self.position,
+            self.position + (self.acceleration - frame_acceleration) * scale,
+            PINK,
+        );
+    }
+}
+
+fn trail_colors(
+    speeds: &[f32],
+    len: usize,
+    color: Color,
+    break_distance: f32,
+    view_params: &ViewParams,
+) -> Vec<Color> {
+    let continuous = || {
+        speeds
+            .iter()
+            .copied()
+            .filter(|&speed| speed <= break_distance)
+    };
+    let slowest = continuous().fold(f32::MAX, f32::min);
+    let fastest = continuous().fold(0.0, f32::max);
+
+    (0..len)
+        .map(|i| {
+            let alpha = view_params.trail_opacity * color.a * (i + 1) as f32 / len as f32;
 
-        self.velocity += acceleration;
-        self.position += self.velocity;
+            match speeds.get(i.saturating_sub(1)) {
+                Some(&speed) if view_params.speed_colored_trails => {
+                    let t =
+                        ((speed - slowest) / (fastest - slowest).max(f32::EPSILON)).clamp(0.0, 1.0);
+                    speed_ramp(t, alpha)
+                }
+                _ => view_params.foreground(alpha),
+            }
+        })
+        .collect()
+}
 
-        self.history.push(self.position);
+fn draw_polyline(points: &[Vec2], thickness: f32, color: impl Fn(usize) -> Color) {
+    if points.len() < 2 {
+        return;
     }
 
-    fn draw(&self) {
-        let planet_x = self.position.x - self.radius / 2.0;
-        let planet_y = self.position.y - self.radius / 2.0;
+    for start in (0..points.len() - 1).step_by(POLYLINE_CHUNK_SEGMENTS) {
+        let end = (start + POLYLINE_CHUNK_SEGMENTS).min(points.len() - 1);
+        let mut vertices = Vec::with_capacity(2 * (end - start + 1));
+        let mut indices = Vec::with_capacity(6 * (end - start));
 
-        let history_len = self.history.len();
+        for i in start..=end {
+            let previous = points[i.saturating_sub(1)];
+            let next = points[(i + 1).min(points.len() - 1)];
+            let offset = (next - previous).normalize_or_zero().perp() * thickness / 2.0;
 
-        if history_len > 1 {
-            for i in 1..history_len {
-                let start = self.history[i - 1];
-                let end = self.history[i];
-                draw_line(
-                    start.x,
-                    start.y,
-                    end.x,
-                    end.y,
-                    1.0,
-                    Color::new(1.0, 1.0, 1.0, 0.1),
-                );
+            for position in [points[i] + offset, points[i] - offset] {
+                vertices.push(macroquad::models::Vertex {
+                    position: position.extend(0.0),
+                    uv: Vec2::ZERO,
+                    color: color(i),
+                });
             }
         }
 
-        draw_circle(planet_x, planet_y, self.radius, self.color);
+        for segment in 0..(end - start) as u16 {
+            let first = 2 * segment;
+            indices.extend_from_slice(&[
+                first,
+                first + 1,
+                first + 2,
+                first + 1,
+                first + 3,
+                first + 2,
+            ]);
+        }
+
+        draw_mesh(&Mesh {
+            vertices,
+            indices,
+            texture: None,
+        });
+    }
+}
+
+fn grid_spacing(zoom: f32) -> f32 {
+    let raw = GRID_PIXELS / zoom;
+    let magnitude = 10f32.powf(raw.log10().floor());
+
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|&spacing| spacing >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn draw_grid(camera: &Camera2D, view_params: &ViewParams) {
+    let zoom = view_params.zoom;
+    let spacing = grid_spacing(zoom);
+    let decimals = (-spacing.log10().floor()).max(0.0) as usize;
+    let corner = camera.screen_to_world(Vec2::ZERO);
+    let opposite = camera.screen_to_world(vec2(screen_width(), screen_height()));
+    let min = corner.min(opposite);
+    let max = corner.max(opposite);
+    let thickness = 1.0 / zoom;
+    let color = view_params.foreground(0.15);
+    let text = TextParams {
+        font_size: LABEL_SIZE,
+        font_scale: 1.0 / zoom,
+        color: view_params.foreground(0.4),
+        ..Default::default()
+    };
+
+    for i in (min.x / spacing).ceil() as i64..=(max.x / spacing).floor() as i64 {
+        let x = i as f32 * spacing;
+        draw_line(x, min.y, x, max.y, thickness, color);
+        draw_text_ex(
+            &format!("{:.*}", decimals, x),
+            x + LABEL_OFFSET / zoom,
+            min.y + LABEL_SIZE as f32 / zoom,
+            text,
+        );
+    }
+
+    for i in (min.y / spacing).ceil() as i64..=(max.y / spacing).floor() as i64 {
+        let y = i as f32 * spacing;
+        draw_line(min.x, y, max.x, y, thickness, color);
+        draw_text_ex(
+            &format!("{:.*}", decimals, y),
+            min.x + LABEL_OFFSET / zoom,
+            y - LABEL_OFFSET / zoom,
+            text,
+        );
+    }
+}
+
+fn integrator_name(integrator: Integrator) -> &'static str {
+    match integrator {
+        Integrator::Euler => "Euler",
+        Integrator::Verlet => "Verlet",
+        Integrator::Rk4 => "RK4",
+    }
+}
+
+fn draw_field(camera: &Camera2D, simulation: &Simulation, view_params: &ViewParams) {
+    let corner = camera.screen_to_world(Vec2::ZERO);
+    let opposite = camera.screen_to_world(vec2(screen_width(), screen_height()));
+    let min = corner.min(opposite);
+    let size = (opposite - corner).abs();
+    let cell = size.x / FIELD_CELLS as f32;
+    let rows = (size.y / cell).ceil() as usize;
+    let cell_corner =
+        |i: usize| min + vec2((i % FIELD_CELLS) as f32, (i / FIELD_CELLS) as f32) * cell;
+
+    let strengths: Vec<f32> = (0..rows * FIELD_CELLS)
+        .map(|i| {
+            let center = cell_corner(i) + Vec2::splat(cell / 2.0);
+            simulation
+                .field_at(center)
+                .length()
+                .max(f32::MIN_POSITIVE)
+                .ln()
+        })
+        .collect();
+    let weakest = strengths.iter().copied().fold(f32::MAX, f32::min);
+    let strongest = strengths.iter().copied().fold(f32::MIN, f32::max);
+
+    for (i, strength) in strengths.iter().enumerate() {
+        let t = ((strength - weakest) / (strongest - weakest).max(f32::EPSILON)).clamp(0.0, 1.0);
+        let corner = cell_corner(i);
+
+        draw_rectangle(
+            corner.x,
+            corner.y,
+            cell,
+            cell,
+            Color::new(t, t * t, 0.3 * (1.0 - t), view_params.field_opacity * t),
+        );
+    }
+}
+
+fn contrasting(color: Color) -> Color {
+    let inverted = vec3(1.0 - color.r, 1.0 - color.g, 1.0 - color.b);
+    let brightest = inverted.max_element();
+
+    if brightest < 0.2 {
+        YELLOW
+    } else {
+        let ring = inverted / brightest;
+        Color::new(ring.x, ring.y, ring.z, 1.0)
+    }
+}
+
+fn draw_arrow(start: Vec2, end: Vec2, color: Color) {
+    let direction = end - start;
+
+    if direction.length_squared() <= f32::EPSILON {
+        return;
     }
+
+    let forward = direction.normalize() * ARROW_HEAD_SIZE;
+    let side = forward.perp() * 0.5;
+
+    draw_line(start.x, start.y, end.x, end.y, 1.0, color);
+    draw_triangle(end + forward, end + side, end - side, color);
 }
 
 struct PlanetParams {
@@ -91,6 +373,14 @@ struct PlanetParams {
     velocity: Vec2,
     mass: f32,
     color: Color,
+    spawn_on_click: bool,
+    spawn_in_orbit: bool,
+    test_particle: bool,
+    constant_density: bool,
+    density_constant: f32,
+    moon_distance: f32,
+    moon_mass: f32,
+    rng: Rng,
 }
 
 impl PlanetParams {
@@ -100,201 +390,3256 @@ impl PlanetParams {
             mass: 10.0,
             velocity: vec2(0.0, 0.0),
             color: Color::new(1.0, 1.0, 1.0, 1.0),
+            spawn_on_click: false,
+            spawn_in_orbit: false,
+            test_particle: false,
+            constant_density: false,
+            density_constant: DENSITY_CONSTANT,
+            moon_distance: 40.0,
+            moon_mass: 0.1,
+            rng: Rng::from_time(),
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        if self.constant_density {
+            self.density_constant * self.mass.cbrt()
+        } else {
+            self.radius
         }
     }
 }
 
-fn update_planets(planets: &mut Vec<Planet>) {
-    let mut planets_clone = planets.clone();
+struct SimParams {
+    paused: bool,
+    time_scale: f32,
+    adaptive: bool,
+    accuracy: f32,
+    comparison: Option<Simulation>,
+    pause_on_collision: bool,
+    pause_speed: f32,
+    pause_reason: Option<String>,
+}
 
-    for planet in planets.iter_mut() {
-        planet.update(&mut planets_clone);
+impl SimParams {
+    fn new() -> Self {
+        SimParams {
+            paused: false,
+            time_scale: 1.0,
+            adaptive: false,
+            accuracy: ACCURACY,
+            comparison: None,
+            pause_on_collision: false,
+            pause_speed: 0.0,
+            pause_reason: None,
+        }
+    }
+
+    fn pause_on(&mut self, report: &StepReport) {
+        if !self.pause_on_collision {
+            return;
+        }
+
+        if let Some(event) = report
+            .collisions
+            .iter()
+            .find(|event| event.speed >= self.pause_speed)
+        {
+            self.paused = true;
+            self.pause_reason = Some(format!(
+                "{} hit {} at {:.1}",
+                event.first, event.second, event.speed
+            ));
+        }
+    }
+
+    fn compare(&mut self, simulation: &Simulation, integrator: Integrator) {
+        let mut comparison = simulation.clone();
+        comparison.integrator = integrator;
+        self.comparison = Some(comparison);
+    }
+
+    fn restart_comparison(&mut self, simulation: &Simulation) {
+        if let Some(integrator) = self.comparison.as_ref().map(|c| c.integrator) {
+            self.compare(simulation, integrator);
+        }
+    }
+
+    fn step_comparison(&mut self, dt: f32) {
+        if let Some(comparison) = &mut self.comparison {
+            comparison.dt = dt;
+            comparison.step();
+        }
+    }
+
+    fn dt(&self) -> f32 {
+        DT * self.time_scale.min(1.0)
     }
-}
 
-fn draw_planets(planets: &[Planet]) {
-    for planet in planets {
-        planet.draw();
+    fn substep_dt(&self, simulation: &Simulation) -> f32 {
+        if self.adaptive {
+            simulation.adaptive_dt(self.dt(), self.accuracy)
+        } else {
+            self.dt()
+        }
     }
 }
 
-fn handle_input(
-    mut camera: Camera2D,
-    planet_params: &PlanetParams,
-    planets: &mut Vec<Planet>,
-    target: &mut usize,
-    spawn_on_click: bool,
-    ui_enabled: &mut bool,
-) {
-    if spawn_on_click && is_mouse_button_pressed(MouseButton::Right) {
-        let position = camera.screen_to_world(mouse_position().into());
+struct Diagnostics {
+    planet_count: usize,
+    energy: f32,
+    initial_energy: f32,
+    momentum: Vec2,
+    angular_momentum: f32,
+    unbound: Vec<bool>,
+    speed_histogram: Vec<usize>,
+    speed_range: (f32, f32),
+    energy_history: VecDeque<f32>,
+    sampled_at: f32,
+    frames_since_classified: usize,
+    collisions: VecDeque<CollisionEvent>,
+}
 
-        let planet = Planet::new(
-            position,
-            planet_params.radius,
-            planet_params.velocity,
-            planet_params.mass,
-            planet_params.color,
-        );
+impl Diagnostics {
+    fn new() -> Self {
+        Diagnostics {
+            planet_count: 0,
+            energy: 0.0,
+            initial_energy: 0.0,
+            momentum: Vec2::ZERO,
+            angular_momentum: 0.0,
+            unbound: Vec::new(),
+            speed_histogram: Vec::new(),
+            speed_range: (0.0, 0.0),
+            energy_history: VecDeque::new(),
+            sampled_at: 0.0,
+            frames_since_classified: 0,
+            collisions: VecDeque::new(),
+        }
+    }
+
+    fn log(&mut self, report: StepReport) {
+        if report.is_empty() {
+            return;
+        }
+
+        if report.removed_non_finite > 0 {
+            eprintln!(
+                "Warning: removed {} planets with non-finite positions",
+                report.removed_non_finite
+            );
+        }
 
-        planets.push(planet);
+        if report.reset_non_finite > 0 {
+            eprintln!(
+                "Warning: reset non-finite velocity of {} planets",
+                report.reset_non_finite
+            );
+        }
+
+        for event in report.collisions {
+            if self.collisions.len() >= MAX_COLLISIONS {
+                self.collisions.pop_front();
+            }
+
+            self.collisions.push_back(event);
+        }
     }
 
-    if is_key_pressed(KeyCode::Z) {
-        *target = (*target + 1) % planets.len();
+    fn update(&mut self, simulation: &Simulation) {
+        self.energy = total_energy(&simulation.planets, simulation.g, simulation.softening)
+            + simulation.halo_energy();
+        self.momentum = total_momentum(&simulation.planets);
+        self.angular_momentum = angular_momentum(&simulation.planets);
+        self.frames_since_classified += 1;
+
+        if simulation.planets.len() != self.planet_count
+            || self.frames_since_classified >= CLASSIFY_INTERVAL
+        {
+            self.unbound = unbound_planets(&simulation.planets, simulation.g, simulation.softening);
+            self.update_speed_histogram(&simulation.planets);
+            self.frames_since_classified = 0;
+        }
+
+        if simulation.planets.len() != self.planet_count {
+            self.planet_count = simulation.planets.len();
+            self.initial_energy = self.energy;
+            self.energy_history.clear();
+        }
+
+        if simulation.time != self.sampled_at {
+            self.sampled_at = simulation.time;
+            self.energy_history.push_back(self.energy);
+
+            while self.energy_history.len() > ENERGY_SAMPLES {
+                self.energy_history.pop_front();
+            }
+        }
     }
 
-    if is_key_pressed(KeyCode::X) {
-        *target = (*target + planets.len() - 1) % planets.len();
+    fn update_speed_histogram(&mut self, planets: &[Planet]) {
+        let speeds: Vec<f32> = planets
+            .iter()
+            .map(|planet| planet.velocity.length())
+            .filter(|speed| speed.is_finite())
+            .collect();
+        let min = speeds.iter().copied().fold(f32::MAX, f32::min);
+        let max = speeds.iter().copied().fold(0.0, f32::max);
+
+        self.speed_histogram = vec![0; HISTOGRAM_BINS];
+        self.speed_range = (min.min(max), max);
+
+        for speed in speeds {
+            let t = (speed - min) / (max - min).max(f32::EPSILON);
+            self.speed_histogram[((t * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1)] +=
+                1;
+        }
     }
 
-    if is_key_pressed(KeyCode::U) {
-        *ui_enabled = !*ui_enabled;
+    fn is_unbound(&self, index: usize) -> bool {
+        self.unbound.get(index).copied().unwrap_or(false)
     }
+}
 
-    let zoom_delta: Vec2 = vec2(mouse_wheel().0, mouse_wheel().1) * ZOOM_SPEED;
-    camera.zoom = (camera.zoom + zoom_delta).max(MIN_ZOOM).min(MAX_ZOOM);
+struct Star {
+    position: Vec2,
+    depth: f32,
+    brightness: f32,
 }
 
-fn draw_ui(planet_params: &mut PlanetParams, spawn_on_click: &mut bool, planets: &mut Vec<Planet>) {
-    widgets::Window::new(hash!(), vec2(470., 50.), vec2(300., 300.))
-        .label("Planet Creator")
-        .ui(&mut *ui::root_ui(), |ui| {
-            ui.tree_node(hash!(), "Settings", |ui| {
-                ui.slider(
-                    hash!("radius"),
-                    "Radius",
-                    1.0..100.0,
-                    &mut planet_params.radius,
-                );
-                ui.separator();
-                ui.slider(
-                    hash!("velocity_x"),
-                    "Velocity X",
-                    -100.0..100.0,
-                    &mut planet_params.velocity.x,
-                );
-                ui.separator();
-                ui.slider(
-                    hash!("velocity_y"),
-                    "Velocity Y",
-                    -100.0..100.0,
-                    &mut planet_params.velocity.y,
-                );
-                ui.separator();
-                ui.slider(hash!("mass"), "Mass", 1.0..1000.0, &mut planet_params.mass);
-                ui.separator();
-                ui.slider(
-                    hash!("color_red"),
-                    "Red",
-                    0.0..1.0,
-                    &mut planet_params.color.r,
-                );
-                ui.separator();
-                ui.slider(
-                    hash!("color_green"),
-                    "Green",
-                    0.0..1.0,
-                    &mut planet_params.color.g,
-                );
-                ui.separator();
-                ui.slider(
-                    hash!("color_blue"),
-                    "Blue",
-                    0.0..1.0,
-                    &mut planet_params.color.b,
-                );
-                ui.separator();
-                ui.checkbox(hash!("spawn_on_click"), "Spawn on click", spawn_on_click);
-            });
-            ui.tree_node(hash!(), "Planets", |ui| {
-                let mut remove_planet_index: Option<usize> = None;
+struct Starfield {
+    stars: Vec<Star>,
+}
 
-                for i in (0..planets.len()).rev() {
-                    ui.tree_node(hash!(), &format!("Planet {}", i + 1), |ui| {
-                        let planet = &mut planets[i];
-                        ui.label(None, &format!("Radius: {}", planet.radius));
-                        ui.separator();
-                        ui.label(None, &format!("Mass: {}", planet.mass));
-                        ui.separator();
-                        ui.label(None, &format!("Velocity: {}", planet.velocity));
-                        ui.separator();
-                        if ui.button(None, "Remove") {
-                            remove_planet_index = Some(i);
-                        }
-                    });
+impl Starfield {
+    fn new() -> Self {
+        Starfield { stars: Vec::new() }
+    }
 
-                    if let Some(index) = remove_planet_index {
-                        planets.remove(index);
-                        break;
+    fn generate(&mut self, count: usize) {
+        if self.stars.len() == count {
+            return;
+        }
+
+        let mut rng = Rng::new(STAR_SEED);
+
+        self.stars = (0..count)
+            .map(|_| Star {
+                position: vec2(rng.range(0.0, STAR_TILE), rng.range(0.0, STAR_TILE)),
+                depth: rng.range(0.05, 0.5),
+                brightness: rng.range(0.2, 1.0),
+            })
+            .collect();
+    }
+
+    fn draw(&self, camera: &Camera2D, view_params: &ViewParams) {
+        let zoom = view_params.zoom;
+        let half_screen = vec2(screen_width(), screen_height()) / 2.0;
+        let tiles = (half_screen / STAR_TILE).ceil().as_ivec2();
+
+        for star in &self.stars {
+            let depth = if view_params.parallax {
+                star.depth
+            } else {
+                1.0
+            };
+            let shifted = star.position - camera.target * zoom * depth;
+            let base = vec2(
+                shifted.x.rem_euclid(STAR_TILE),
+                shifted.y.rem_euclid(STAR_TILE),
+            ) - STAR_TILE / 2.0;
+
+            for tile_x in -tiles.x..=tiles.x {
+                for tile_y in -tiles.y..=tiles.y {
+                    let offset = base + vec2(tile_x as f32, tile_y as f32) * STAR_TILE;
+
+                    if offset.abs().cmpgt(half_screen).any() {
+                        continue;
                     }
+
+                    let position = camera.target + offset / zoom;
+                    draw_rectangle(
+                        position.x,
+                        position.y,
+                        1.0 / zoom,
+                        1.0 / zoom,
+                        view_params.foreground(star.brightness),
+                    );
                 }
-            });
-        });
+            }
+        }
+    }
 }
 
-fn window_conf() -> Conf {
-    Conf {
-        window_title: "Planets".to_owned(),
-        window_width: 800,
-        window_height: 600,
-        ..Default::default()
-    }
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    Inertial,
+    Centered,
+    CoMoving,
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut planet_params = PlanetParams::new();
-    let mut camera =
-        Camera2D::from_display_rect(Rect::new(0.0, 0.0, screen_width(), screen_height()));
-    let mut target = 0;
-    let mut planets = vec![
-        Planet::new(
-            vec2(screen_width() / 2.0, screen_height() / 2.0),
-            5.0,
-            vec2(-0.1, -0.1),
-            5.0,
-            Color::new(1.0, 0.0, 0.0, 1.0),
-        ),
-        Planet::new(
-            vec2(screen_width() / 2.0 + 100.0, screen_height() / 2.0),
-            10.0,
-            vec2(0.1, 0.1),
-            10.0,
-            Color::new(1.0, 1.0, 1.0, 1.0),
-        ),
-    ];
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BodyColor {
+    User,
+    Speed,
+}
+
+fn speed_ramp(t: f32, alpha: f32) -> Color {
+    Color::new(t, 0.2, 1.0 - t, alpha)
+}
 
-    let mut spawn_on_click = false;
-    let mut ui_enabled = false;
+struct ViewParams {
+    ui_enabled: bool,
+    show_velocity: bool,
+    velocity_scale: f32,
+    show_acceleration: bool,
+    acceleration_scale: f32,
+    show_center_of_mass: bool,
+    show_impacts: bool,
+    impact_horizon: f32,
+    follow_center_of_mass: bool,
+    zoom: f32,
+    pan: Vec2,
+    free_camera: bool,
+    trail_opacity: f32,
+    speed_colored_trails: bool,
+    show_stats: bool,
+    show_help: bool,
+    screenshot_requested: bool,
+    fit_requested: bool,
+    highlight_unbound: bool,
+    show_grid: bool,
+    show_field: bool,
+    field_opacity: f32,
+    show_minimap: bool,
+    muted: bool,
+    volume: f32,
+    show_starfield: bool,
+    star_count: usize,
+    parallax: bool,
+    starfield: Starfield,
+    background: Color,
+    show_labels: bool,
+    label_name: bool,
+    label_mass: bool,
+    label_speed: bool,
+    show_apsides: bool,
+    show_orbit_ellipse: bool,
+    frame: Frame,
+    body_color: BodyColor,
+    min_zoom: f32,
+    max_zoom: f32,
+    selection: HashSet<usize>,
+    group_velocity: Vec2,
+    group_offset: Vec2,
+}
 
-    loop {
-        clear_background(BLACK);
+impl ViewParams {
+    fn new() -> Self {
+        ViewParams {
+            ui_enabled: false,
+            show_velocity: false,
+            velocity_scale: VELOCITY_SCALE,
+            show_acceleration: false,
+            acceleration_scale: ACCELERATION_SCALE,
+            show_center_of_mass: false,
+            show_impacts: true,
+            impact_horizon: IMPACT_HORIZON,
+            follow_center_of_mass: false,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            free_camera: false,
+            trail_opacity: TRAIL_OPACITY,
+            speed_colored_trails: false,
+            show_stats: false,
+            show_help: false,
+            screenshot_requested: false,
+            fit_requested: false,
+            highlight_unbound: true,
+            show_grid: false,
+            show_field: false,
+            field_opacity: 0.5,
+            show_minimap: false,
+            muted: false,
+            volume: 0.5,
+            show_starfield: false,
+            star_count: STAR_COUNT,
+            parallax: true,
+            starfield: Starfield::new(),
+            background: BLACK,
+            show_labels: false,
+            label_name: true,
+            label_mass: false,
+            label_speed: false,
+            show_apsides: false,
+            show_orbit_ellipse: true,
+            frame: Frame::Inertial,
+            body_color: BodyColor::User,
+            min_zoom: MIN_ZOOM,
+            max_zoom: MAX_ZOOM,
+            selection: HashSet::new(),
+            group_velocity: Vec2::ZERO,
+            group_offset: Vec2::ZERO,
+        }
+    }
 
-        if !planets.is_empty() {
-            camera.target = planets[target].position;
+    fn effective_volume(&self) -> f32 {
+        if self.muted {
+            0.0
         } else {
-            target = 0;
+            self.volume
         }
+    }
 
-        update_planets(&mut planets);
-        handle_input(
-            camera,
-            &planet_params,
-            &mut planets,
-            &mut target,
-            spawn_on_click,
-            &mut ui_enabled,
-        );
+    fn light_background(&self) -> bool {
+        let background = self.background;
 
-        set_camera(&camera);
-        draw_planets(&planets);
-        set_default_camera();
+        0.2126 * background.r + 0.7152 * background.g + 0.0722 * background.b > 0.5
+    }
+
+    fn foreground(&self, alpha: f32) -> Color {
+        if self.light_background() {
+            Color::new(0.0, 0.0, 0.0, alpha)
+        } else {
+            Color::new(1.0, 1.0, 1.0, alpha)
+        }
+    }
 
-        if ui_enabled {
-            draw_ui(&mut planet_params, &mut spawn_on_click, &mut planets);
+    fn zoom_to_fit(&mut self, camera: &mut Camera2D, planets: &[Planet]) {
+        if planets.is_empty() {
+            return;
         }
 
-        next_frame().await
+        let (min, max) = planets.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), planet| {
+                (
+                    min.min(planet.position - planet.radius),
+                    max.max(planet.position + planet.radius),
+                )
+            },
+        );
+        let size = (max - min) * FIT_MARGIN;
+
+        self.zoom = (screen_width() / size.x)
+            .min(screen_height() / size.y)
+            .clamp(self.min_zoom, self.max_zoom);
+        self.free_camera = true;
+        camera.target = (min + max) / 2.0;
+    }
+
+    fn pan(&mut self, camera: &mut Camera2D, delta: Vec2) {
+        if self.free_camera {
+            camera.target += delta;
+        } else {
+            self.pan += delta;
+        }
+    }
+}
+
+fn trail_points(simulation: &Simulation, planet: &Planet, reference: Option<&Planet>) -> Vec<Vec2> {
+    let mut trail: Vec<Vec2> = match reference {
+        Some(reference) => {
+            let mut trail: Vec<Vec2> = planet
+                .history
+                .iter()
+                .rev()
+                .zip(reference.history.iter().rev())
+                .map(|(&position, &origin)| {
+                    reference.position + simulation.separation(origin, position)
+                })
+                .collect();
+            trail.reverse();
+            trail
+        }
+        None => planet.history.iter().copied().collect(),
+    };
+
+    // Sparse samples stop short of the body, so close the gap to where it is now.
+    let current = match reference {
+        Some(reference) => {
+            reference.position + simulation.separation(reference.position, planet.position)
+        }
+        None => planet.position,
+    };
+    if trail.last().is_some_and(|&last| last != current) {
+        trail.push(current);
+    }
+
+    trail
+}
+
+fn draw_planets(
+    simulation: &Simulation,
+    target: usize,
+    view_params: &ViewParams,
+    diagnostics: &Diagnostics,
+) {
+    let planets = &simulation.planets;
+    let break_distance = if simulation.boundary == Boundary::Wrap {
+        simulation.bounds.w.min(simulation.bounds.h) / 2.0
+    } else {
+        f32::INFINITY
+    };
+
+    let reference = planets
+        .get(target)
+        .filter(|_| view_params.frame == Frame::CoMoving);
+
+    let (slowest, fastest) = planets
+        .iter()
+        .map(|planet| planet.velocity.length())
+        .fold((f32::MAX, 0.0f32), |(slowest, fastest), speed| {
+            (slowest.min(speed), fastest.max(speed))
+        });
+
+    for (i, planet) in planets.iter().enumerate() {
+        let color = if view_params.highlight_unbound && diagnostics.is_unbound(i) {
+            UNBOUND_COLOR
+        } else if view_params.body_color == BodyColor::Speed {
+            let t = (planet.velocity.length() - slowest) / (fastest - slowest).max(f32::EPSILON);
+            speed_ramp(t.clamp(0.0, 1.0), planet.color.a)
+        } else {
+            planet.color
+        };
+
+        let trail = trail_points(simulation, planet, reference);
+        planet.draw(&trail, color, break_distance, view_params);
+    }
+
+    if let Some(planet) = planets.get(target) {
+        draw_circle_lines(
+            planet.position.x,
+            planet.position.y,
+            planet.radius + SELECTION_GAP / view_params.zoom,
+            1.0 / view_params.zoom,
+            contrasting(planet.color),
+        );
+    }
+
+    for &i in &view_params.selection {
+        if let Some(planet) = planets.get(i) {
+            draw_circle_lines(
+                planet.position.x,
+                planet.position.y,
+                planet.radius + SELECTION_GAP * 2.0 / view_params.zoom,
+                1.5 / view_params.zoom,
+                SELECTION_COLOR,
+            );
+        }
+    }
+
+    if view_params.show_apsides {
+        draw_orbit(simulation, target, view_params);
+    }
+
+    if view_params.show_labels {
+        for (i, planet) in planets.iter().enumerate() {
+            planet.draw_label(i, view_params);
+        }
+    }
+
+    if view_params.show_velocity {
+        for planet in planets {
+            let frame_velocity = reference.map_or(Vec2::ZERO, |reference| reference.velocity);
+            planet.draw_velocity(frame_velocity, view_params.velocity_scale);
+        }
+    }
+
+    if view_params.show_acceleration {
+        for planet in planets {
+            let frame_acceleration =
+                reference.map_or(Vec2::ZERO, |reference| reference.acceleration);
+            planet.draw_acceleration(frame_acceleration, view_params.acceleration_scale);
+        }
+    }
+
+    if view_params.show_impacts && planets.len() <= IMPACT_MAX_BODIES {
+        draw_impacts(planets, view_params);
+    }
+
+    if view_params.show_center_of_mass && !planets.is_empty() {
+        let center = center_of_mass(planets);
+
+        draw_line(
+            center.x - MARKER_SIZE,
+            center.y,
+            center.x + MARKER_SIZE,
+            center.y,
+            1.0,
+            GREEN,
+        );
+        draw_line(
+            center.x,
+            center.y - MARKER_SIZE,
+            center.x,
+            center.y + MARKER_SIZE,
+            1.0,
+            GREEN,
+        );
+    }
+}
+
+fn draw_impacts(planets: &[Planet], view_params: &ViewParams) {
+    let scale = 1.0 / view_params.zoom;
+
+    for (i, j, time) in predicted_impacts(planets, view_params.impact_horizon) {
+        let (a, b) = (planets[i].position, planets[j].position);
+        let middle = (a + b) / 2.0;
+
+        draw_line(a.x, a.y, b.x, b.y, scale, IMPACT_COLOR);
+        draw_text_ex(
+            &format!("{:.1}s", time),
+            middle.x + LABEL_OFFSET * scale,
+            middle.y - LABEL_OFFSET * scale,
+            TextParams {
+                font_size: LABEL_SIZE,
+                font_scale: scale,
+                color: IMPACT_COLOR,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn draw_orbit(simulation: &Simulation, target: usize, view_params: &ViewParams) {
+    let planets = &simulation.planets;
+    let Some(primary) = dominant_body(planets, target) else {
+        return;
+    };
+    let Some(elements) = planets
+        .get(target)
+        .and_then(|planet| orbital_elements(planet, &planets[primary], simulation.g))
+    else {
+        return;
+    };
+
+    let scale = 1.0 / view_params.zoom;
+    let marker = |position: Vec2, text: &str, color: Color| {
+        draw_circle(position.x, position.y, MARKER_SIZE / 2.0 * scale, color);
+        draw_text_ex(
+            text,
+            position.x + LABEL_OFFSET * scale,
+            position.y - LABEL_OFFSET * scale,
+            TextParams {
+                font_size: LABEL_SIZE,
+                font_scale: scale,
+                color,
+                ..Default::default()
+            },
+        );
+    };
+
+    let Some(apoapsis) = elements.apoapsis else {
+        let planet = &planets[target];
+        marker(planet.position + vec2(0.0, planet.radius), "escape", RED);
+        return;
+    };
+
+    if view_params.show_orbit_ellipse {
+        let center = (elements.periapsis + apoapsis) / 2.0;
+        let major = elements.periapsis - center;
+        let minor = major.perp() * (1.0 - elements.eccentricity * elements.eccentricity).sqrt();
+        let points: Vec<Vec2> = (0..=ORBIT_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / ORBIT_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + major * angle.cos() + minor * angle.sin()
+            })
+            .collect();
+
+        draw_polyline(&points, scale, |_| view_params.foreground(0.25));
+    }
+
+    marker(elements.periapsis, "Pe", ORANGE);
+    marker(apoapsis, "Ap", SKYBLUE);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    NextTarget,
+    PreviousTarget,
+    ToggleUi,
+    ToggleVelocity,
+    ToggleStats,
+    ToggleHelp,
+    ClearTrails,
+    ZoomToFit,
+    Screenshot,
+    Pause,
+    Step,
+    Reset,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    LockCamera,
+    DeleteSelection,
+    ClearSelection,
+    Undo,
+    Redo,
+}
+
+const KEY_BINDINGS: [(Action, &[KeyCode], &str); 21] = [
+    (Action::NextTarget, &[KeyCode::Z], "Select next planet"),
+    (
+        Action::PreviousTarget,
+        &[KeyCode::X],
+        "Select previous planet",
+    ),
+    (Action::ToggleUi, &[KeyCode::U], "Toggle UI"),
+    (
+        Action::ToggleVelocity,
+        &[KeyCode::V],
+        "Toggle velocity arrows",
+    ),
+    (Action::ToggleStats, &[KeyCode::F], "Toggle stats"),
+    (
+        Action::ToggleHelp,
+        &[KeyCode::H, KeyCode::Slash],
+        "Toggle this help",
+    ),
+    (Action::ClearTrails, &[KeyCode::T], "Clear trails"),
+    (Action::ZoomToFit, &[KeyCode::G], "Zoom to fit"),
+    (Action::Screenshot, &[KeyCode::P], "Save screenshot"),
+    (Action::Pause, &[KeyCode::Space], "Pause"),
+    (
+        Action::Step,
+        &[KeyCode::Period],
+        "Step while paused (hold to repeat)",
+    ),
+    (Action::Reset, &[KeyCode::R], "Reset scene"),
+    (Action::PanUp, &[KeyCode::W, KeyCode::Up], "Pan up"),
+    (Action::PanDown, &[KeyCode::S, KeyCode::Down], "Pan down"),
+    (Action::PanLeft, &[KeyCode::A, KeyCode::Left], "Pan left"),
+    (Action::PanRight, &[KeyCode::D, KeyCode::Right], "Pan right"),
+    (Action::LockCamera, &[KeyCode::L], "Follow target again"),
+    (
+        Action::DeleteSelection,
+        &[KeyCode::Delete],
+        "Delete selected planets",
+    ),
+    (
+        Action::ClearSelection,
+        &[KeyCode::Escape],
+        "Clear selection",
+    ),
+    (Action::Undo, &[KeyCode::Z], "Undo"),
+    (Action::Redo, &[KeyCode::Y], "Redo"),
+];
+
+const MOUSE_BINDINGS: [(&str, &str); 5] = [
+    ("Left drag", "Select and move planet"),
+    ("Left drag empty", "Box select (Shift adds)"),
+    ("Right drag", "Slingshot a new planet"),
+    ("Middle drag", "Pan"),
+    ("Wheel", "Zoom"),
+];
+
+fn action_keys(action: Action) -> &'static [KeyCode] {
+    KEY_BINDINGS
+        .iter()
+        .find(|(bound, _, _)| *bound == action)
+        .map_or(&[], |&(_, keys, _)| keys)
+}
+
+fn needs_control(action: Action) -> bool {
+    matches!(action, Action::Undo | Action::Redo)
+}
+
+fn is_action_pressed(action: Action) -> bool {
+    let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
+    control == needs_control(action) && action_keys(action).iter().any(|&key| is_key_pressed(key))
+}
+
+fn is_action_down(action: Action) -> bool {
+    action_keys(action).iter().any(|&key| is_key_down(key))
+}
+
+fn key_names(action: Action) -> String {
+    let modifier = if needs_control(action) { "Ctrl+" } else { "" };
+
+    action_keys(action)
+        .iter()
+        .map(|key| match key {
+            KeyCode::Period => ".".to_owned(),
+            KeyCode::Slash => "?".to_owned(),
+            key => format!("{}{:?}", modifier, key),
+        })
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+fn draw_help(view_params: &ViewParams) {
+    let lines: Vec<(String, &str)> = KEY_BINDINGS
+        .iter()
+        .map(|&(action, _, description)| (key_names(action), description))
+        .chain(
+            MOUSE_BINDINGS
+                .iter()
+                .map(|&(input, description)| (input.to_owned(), description)),
+        )
+        .collect();
+
+    let column = lines
+        .iter()
+        .map(|(input, _)| measure_text(input, None, 20, 1.0).width)
+        .fold(0.0, f32::max)
+        + HELP_PADDING;
+    let width = column
+        + lines
+            .iter()
+            .map(|(_, description)| measure_text(description, None, 20, 1.0).width)
+            .fold(0.0, f32::max)
+        + HELP_PADDING * 2.0;
+    let height = lines.len() as f32 * HELP_LINE_HEIGHT + HELP_PADDING * 2.0;
+    let x = (screen_width() - width) / 2.0;
+    let y = (screen_height() - height) / 2.0;
+
+    let mut panel = view_params.background;
+    panel.a = 0.85;
+    draw_rectangle(x, y, width, height, panel);
+
+    for (i, (input, description)) in lines.iter().enumerate() {
+        let baseline = y + HELP_PADDING + (i + 1) as f32 * HELP_LINE_HEIGHT - 6.0;
+
+        draw_text(
+            input,
+            x + HELP_PADDING,
+            baseline,
+            20.0,
+            view_params.foreground(1.0),
+        );
+        draw_text(
+            description,
+            x + HELP_PADDING + column,
+            baseline,
+            20.0,
+            view_params.foreground(0.8),
+        );
+    }
+}
+
+struct Minimap {
+    screen: Rect,
+    world: Rect,
+}
+
+impl Minimap {
+    fn new(camera: &Camera2D, planets: &[Planet]) -> Self {
+        let screen = Rect::new(
+            screen_width() - MINIMAP_SIZE.x - MINIMAP_MARGIN,
+            screen_height() - MINIMAP_SIZE.y - MINIMAP_MARGIN,
+            MINIMAP_SIZE.x,
+            MINIMAP_SIZE.y,
+        );
+
+        let view = Minimap::view(camera);
+        let (min, max) = planets.iter().fold(
+            (view.point(), view.point() + view.size()),
+            |(min, max), planet| {
+                (
+                    min.min(planet.position - planet.radius),
+                    max.max(planet.position + planet.radius),
+                )
+            },
+        );
+
+        let scale = ((max - min) / screen.size()).max_element();
+        let size = screen.size() * scale;
+        let corner = (min + max - size) / 2.0;
+
+        Minimap {
+            screen,
+            world: Rect::new(corner.x, corner.y, size.x, size.y),
+        }
+    }
+
+    fn view(camera: &Camera2D) -> Rect {
+        let first = camera.screen_to_world(Vec2::ZERO);
+        let second = camera.screen_to_world(vec2(screen_width(), screen_height()));
+        let min = first.min(second);
+        let max = first.max(second);
+
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    fn to_screen(&self, position: Vec2) -> Vec2 {
+        self.screen.point()
+            + (position - self.world.point()) / self.world.size() * self.screen.size()
+    }
+
+    fn to_world(&self, position: Vec2) -> Vec2 {
+        self.world.point()
+            + (position - self.screen.point()) / self.screen.size() * self.world.size()
+    }
+
+    fn draw(&self, camera: &Camera2D, planets: &[Planet], view_params: &ViewParams) {
+        let mut panel = view_params.background;
+        panel.a = 0.8;
+        draw_rectangle(
+            self.screen.x,
+            self.screen.y,
+            self.screen.w,
+            self.screen.h,
+            panel,
+        );
+        draw_rectangle_lines(
+            self.screen.x,
+            self.screen.y,
+            self.screen.w,
+            self.screen.h,
+            1.0,
+            view_params.foreground(0.5),
+        );
+
+        for planet in planets {
+            let position = self.to_screen(planet.position);
+            draw_circle(position.x, position.y, 1.5, planet.color);
+        }
+
+        let view = Minimap::view(camera);
+        let min = self.to_screen(view.point());
+        let max = self.to_screen(view.point() + view.size());
+        draw_rectangle_lines(
+            min.x,
+            min.y,
+            max.x - min.x,
+            max.y - min.y,
+            1.0,
+            view_params.foreground(0.8),
+        );
+    }
+}
+
+struct InputState {
+    dragging: Option<usize>,
+    spawn_start: Option<Vec2>,
+    pan_anchor: Option<Vec2>,
+    reset_requested: bool,
+    step_requested: bool,
+    step_held: f32,
+    box_start: Option<Vec2>,
+    undo_requested: bool,
+    redo_requested: bool,
+    snapshot: Option<Vec<Planet>>,
+}
+
+impl InputState {
+    fn new() -> Self {
+        InputState {
+            dragging: None,
+            spawn_start: None,
+            pan_anchor: None,
+            reset_requested: false,
+            step_requested: false,
+            step_held: 0.0,
+            box_start: None,
+            undo_requested: false,
+            redo_requested: false,
+            snapshot: None,
+        }
+    }
+}
+
+fn remove_planets(
+    simulation: &mut Simulation,
+    selection: &mut HashSet<usize>,
+    target: &mut usize,
+    remove: impl Fn(usize) -> bool,
+) {
+    let mut kept = 0;
+    let mut remapped = HashSet::new();
+    let mut new_target = 0;
+    let mut index = 0;
+
+    simulation.planets.retain(|_| {
+        let keep = !remove(index);
+
+        if keep {
+            if selection.contains(&index) {
+                remapped.insert(kept);
+            }
+
+            if index == *target {
+                new_target = kept;
+            }
+
+            kept += 1;
+        }
+
+        index += 1;
+        keep
+    });
+
+    *selection = remapped;
+    *target = new_target;
+}
+
+fn follow_planets(
+    report: &StepReport,
+    target: &mut usize,
+    view_params: &mut ViewParams,
+    input_state: &mut InputState,
+) {
+    if report.remap.is_none() {
+        return;
+    }
+
+    *target = report.new_index(*target).unwrap_or(0);
+    view_params.selection = view_params
+        .selection
+        .iter()
+        .filter_map(|&index| report.new_index(index))
+        .collect();
+    input_state.dragging = input_state
+        .dragging
+        .and_then(|index| report.new_index(index));
+}
+
+fn planet_at(planets: &[Planet], position: Vec2) -> Option<usize> {
+    planets
+        .iter()
+        .enumerate()
+        .filter(|(_, planet)| planet.position.distance(position) <= planet.radius)
+        .min_by(|(_, a), (_, b)| {
+            a.position
+                .distance_squared(position)
+                .total_cmp(&b.position.distance_squared(position))
+        })
+        .map(|(i, _)| i)
+}
+
+fn prospective_planet(
+    planet_params: &PlanetParams,
+    simulation: &Simulation,
+    target: usize,
+    position: Vec2,
+    drag: Vec2,
+) -> Option<Planet> {
+    let velocity = if planet_params.spawn_in_orbit {
+        simulation
+            .planets
+            .get(target)
+            .filter(|around| around.position.distance(position) > around.radius)
+            .map(|around| {
+                around.velocity + circular_orbit_velocity(around, position, simulation.g)
+            })?
+    } else if drag.length() > f32::EPSILON {
+        drag * SLINGSHOT_SCALE
+    } else {
+        planet_params.velocity
+    };
+
+    let mass = if planet_params.test_particle {
+        0.0
+    } else {
+        planet_params.mass
+    };
+
+    Some(Planet::new(
+        position,
+        planet_params.radius(),
+        velocity,
+        mass,
+        planet_params.color,
+    ))
+}
+
+fn draw_spawn_preview(
+    camera: &Camera2D,
+    input_state: &InputState,
+    planet_params: &PlanetParams,
+    simulation: &Simulation,
+    target: usize,
+) {
+    if !planet_params.spawn_on_click || ui::root_ui().is_mouse_over(mouse_position().into()) {
+        return;
+    }
+
+    let mouse = camera.screen_to_world(mouse_position().into());
+    let position = input_state.spawn_start.unwrap_or(mouse);
+
+    if input_state.spawn_start.is_some() {
+        draw_arrow(position, mouse, SKYBLUE);
+    }
+
+    let Some(planet) = prospective_planet(
+        planet_params,
+        simulation,
+        target,
+        position,
+        mouse - position,
+    ) else {
+        return;
+    };
+
+    let path = simulation.predict_trajectory(&planet);
+
+    for segment in path.windows(2).step_by(2) {
+        draw_line(
+            segment[0].x,
+            segment[0].y,
+            segment[1].x,
+            segment[1].y,
+            1.0,
+            Color::new(0.5, 0.8, 1.0, 0.6),
+        );
+    }
+}
+
+fn handle_input(
+    camera: &mut Camera2D,
+    planet_params: &PlanetParams,
+    sim_params: &mut SimParams,
+    simulation: &mut Simulation,
+    target: &mut usize,
+    view_params: &mut ViewParams,
+    input_state: &mut InputState,
+) {
+    let previous_target = *target;
+
+    if planet_params.spawn_on_click && is_mouse_button_pressed(MouseButton::Right) {
+        input_state.spawn_start = Some(camera.screen_to_world(mouse_position().into()));
+    }
+
+    if let Some(position) = input_state.spawn_start {
+        if !is_mouse_button_down(MouseButton::Right) {
+            let drag = camera.screen_to_world(mouse_position().into()) - position;
+
+            if let Some(planet) =
+                prospective_planet(planet_params, simulation, *target, position, drag)
+            {
+                input_state.snapshot = Some(snapshot_of(&simulation.planets));
+                simulation.planets.push(planet);
+            }
+
+            input_state.spawn_start = None;
+        }
+    }
+
+    let minimap = Minimap::new(camera, &simulation.planets);
+    let on_minimap = view_params.show_minimap && minimap.screen.contains(mouse_position().into());
+
+    if is_mouse_button_pressed(MouseButton::Left) && on_minimap {
+        view_params.free_camera = true;
+        camera.target = minimap.to_world(mouse_position().into());
+    } else if is_mouse_button_pressed(MouseButton::Left)
+        && !ui::root_ui().is_mouse_over(mouse_position().into())
+    {
+        let position = camera.screen_to_world(mouse_position().into());
+
+        if let Some(index) = planet_at(&simulation.planets, position) {
+            *target = index;
+            input_state.dragging = Some(index);
+        } else {
+            input_state.box_start = Some(mouse_position().into());
+        }
+    }
+
+    if let Some(start) = input_state.box_start {
+        if !is_mouse_button_down(MouseButton::Left) {
+            let first = camera.screen_to_world(start);
+            let second = camera.screen_to_world(mouse_position().into());
+            let (min, max) = (first.min(second), first.max(second));
+
+            if !is_key_down(KeyCode::LeftShift) && !is_key_down(KeyCode::RightShift) {
+                view_params.selection.clear();
+            }
+
+            view_params.selection.extend(
+                simulation
+                    .planets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, planet)| {
+                        planet.position.cmpge(min).all() && planet.position.cmple(max).all()
+                    })
+                    .map(|(i, _)| i),
+            );
+            input_state.box_start = None;
+        }
+    }
+
+    if let Some(index) = input_state.dragging {
+        if let Some(planet) = simulation.planets.get_mut(index) {
+            planet.position = camera.screen_to_world(mouse_position().into());
+            planet.velocity = Vec2::ZERO;
+            planet.acceleration = Vec2::ZERO;
+
+            if !is_mouse_button_down(MouseButton::Left) {
+                planet.history.clear();
+                input_state.dragging = None;
+            }
+        } else {
+            input_state.dragging = None;
+        }
+    }
+
+    let count = simulation.planets.len();
+
+    if is_action_pressed(Action::NextTarget) && count > 0 {
+        *target = (*target + 1) % count;
+    }
+
+    if is_action_pressed(Action::PreviousTarget) && count > 0 {
+        *target = (*target + count - 1) % count;
+    }
+
+    if is_action_pressed(Action::ToggleUi) {
+        view_params.ui_enabled = !view_params.ui_enabled;
+    }
+
+    if is_action_pressed(Action::ToggleVelocity) {
+        view_params.show_velocity = !view_params.show_velocity;
+    }
+
+    if is_action_pressed(Action::ToggleStats) {
+        view_params.show_stats = !view_params.show_stats;
+    }
+
+    if is_action_pressed(Action::ToggleHelp) {
+        view_params.show_help = !view_params.show_help;
+    }
+
+    if is_action_pressed(Action::ClearTrails) {
+        simulation.clear_trails();
+    }
+
+    if is_action_pressed(Action::DeleteSelection) && !view_params.selection.is_empty() {
+        input_state.snapshot = Some(snapshot_of(&simulation.planets));
+        let selection = std::mem::take(&mut view_params.selection);
+        remove_planets(simulation, &mut view_params.selection, target, |i| {
+            selection.contains(&i)
+        });
+    }
+
+    if is_action_pressed(Action::ClearSelection) {
+        view_params.selection.clear();
+    }
+
+    if is_action_pressed(Action::ZoomToFit) {
+        view_params.fit_requested = true;
+    }
+
+    if view_params.fit_requested {
+        view_params.zoom_to_fit(camera, &simulation.planets);
+        view_params.fit_requested = false;
+    }
+
+    if is_action_pressed(Action::Screenshot) {
+        view_params.screenshot_requested = true;
+    }
+
+    if is_action_pressed(Action::Pause) {
+        sim_params.paused = !sim_params.paused;
+    }
+
+    if sim_params.paused && is_action_down(Action::Step) {
+        if is_action_pressed(Action::Step) {
+            input_state.step_held = 0.0;
+            input_state.step_requested = true;
+        } else {
+            input_state.step_held += get_frame_time();
+            input_state.step_requested = input_state.step_held >= STEP_REPEAT_DELAY;
+        }
+    }
+
+    if is_action_pressed(Action::Reset) {
+        input_state.reset_requested = true;
+    }
+
+    if is_action_pressed(Action::Undo) {
+        input_state.undo_requested = true;
+    }
+
+    if is_action_pressed(Action::Redo) {
+        input_state.redo_requested = true;
+    }
+
+    let wheel = mouse_wheel().1;
+
+    if wheel != 0.0 && !ui::root_ui().is_mouse_over(mouse_position().into()) {
+        let before = camera.screen_to_world(mouse_position().into());
+
+        view_params.zoom = (view_params.zoom * (1.0 + ZOOM_SPEED).powf(wheel.signum()))
+            .clamp(view_params.min_zoom, view_params.max_zoom);
+
+        let zoomed = Camera2D {
+            zoom: vec2(2.0 / screen_width(), -2.0 / screen_height()) * view_params.zoom,
+            ..*camera
+        };
+        let after = zoomed.screen_to_world(mouse_position().into());
+
+        view_params.pan(camera, before - after);
+    }
+
+    if is_mouse_button_pressed(MouseButton::Middle) {
+        view_params.free_camera = true;
+        input_state.pan_anchor = Some(camera.screen_to_world(mouse_position().into()));
+    }
+
+    if let Some(anchor) = input_state.pan_anchor {
+        camera.target += anchor - camera.screen_to_world(mouse_position().into());
+
+        if !is_mouse_button_down(MouseButton::Middle) {
+            input_state.pan_anchor = None;
+        }
+    }
+
+    let mut direction = Vec2::ZERO;
+
+    if is_action_down(Action::PanUp) {
+        direction.y -= 1.0;
+    }
+
+    if is_action_down(Action::PanDown) {
+        direction.y += 1.0;
+    }
+
+    if is_action_down(Action::PanLeft) {
+        direction.x -= 1.0;
+    }
+
+    if is_action_down(Action::PanRight) {
+        direction.x += 1.0;
+    }
+
+    if direction != Vec2::ZERO {
+        view_params.free_camera = true;
+        camera.target += direction.normalize() * PAN_SPEED / view_params.zoom * get_frame_time();
+    }
+
+    if is_action_pressed(Action::LockCamera) {
+        view_params.free_camera = false;
+        view_params.pan = Vec2::ZERO;
+    }
+
+    if *target != previous_target {
+        view_params.pan = Vec2::ZERO;
+    }
+}
+
+fn draw_ui(
+    planet_params: &mut PlanetParams,
+    sim_params: &mut SimParams,
+    view_params: &mut ViewParams,
+    simulation: &mut Simulation,
+    target: &mut usize,
+    scene_state: &mut SceneState,
+    diagnostics: &mut Diagnostics,
+) {
+    widgets::Window::new(hash!(), vec2(470., 50.), vec2(300., 300.))
+        .label("Planet Creator")
+        .ui(&mut ui::root_ui(), |ui| {
+            ui.tree_node(hash!(), "Settings", |ui| {
+                if planet_params.constant_density {
+                    ui.label(None, &format!("Radius: {:.2}", planet_params.radius()));
+                } else {
+                    ui.slider(
+                        hash!("radius"),
+                        "Radius",
+                        1.0..100.0,
+                        &mut planet_params.radius,
+                    );
+                }
+                ui.checkbox(
+                    hash!("constant_density"),
+                    "Constant density",
+                    &mut planet_params.constant_density,
+                );
+                if planet_params.constant_density {
+                    ui.slider(
+                        hash!("density_constant"),
+                        "Density constant",
+                        0.1..10.0,
+                        &mut planet_params.density_constant,
+                    );
+                }
+                ui.separator();
+                ui.slider(
+                    hash!("velocity_x"),
+                    "Velocity X",
+                    -100.0..100.0,
+                    &mut planet_params.velocity.x,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("velocity_y"),
+                    "Velocity Y",
+                    -100.0..100.0,
+                    &mut planet_params.velocity.y,
+                );
+                ui.separator();
+                ui.slider(hash!("mass"), "Mass", 1.0..1000.0, &mut planet_params.mass);
+                ui.separator();
+                ui.slider(
+                    hash!("color_red"),
+                    "Red",
+                    0.0..1.0,
+                    &mut planet_params.color.r,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("color_green"),
+                    "Green",
+                    0.0..1.0,
+                    &mut planet_params.color.g,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("color_blue"),
+                    "Blue",
+                    0.0..1.0,
+                    &mut planet_params.color.b,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("color_alpha"),
+                    "Alpha",
+                    0.0..1.0,
+                    &mut planet_params.color.a,
+                );
+                if ui.button(None, "Randomize color") {
+                    planet_params.color =
+                        macroquad::color::hsl_to_rgb(planet_params.rng.next_f32(), 1.0, 0.6);
+                }
+                ui.separator();
+                ui.checkbox(
+                    hash!("spawn_on_click"),
+                    "Spawn on click",
+                    &mut planet_params.spawn_on_click,
+                );
+                ui.checkbox(
+                    hash!("spawn_in_orbit"),
+                    &format!(
+                        "Spawn orbiting {}",
+                        simulation
+                            .planets
+                            .get(*target)
+                            .map(|planet| planet.display_name(*target))
+                            .unwrap_or_default()
+                    ),
+                    &mut planet_params.spawn_in_orbit,
+                );
+                ui.checkbox(
+                    hash!("test_particle"),
+                    "Test particle",
+                    &mut planet_params.test_particle,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("moon_distance"),
+                    "Moon distance",
+                    5.0..500.0,
+                    &mut planet_params.moon_distance,
+                );
+                ui.slider(
+                    hash!("moon_mass"),
+                    "Moon mass",
+                    0.0..100.0,
+                    &mut planet_params.moon_mass,
+                );
+                if let Some(hill) = hill_radius(simulation, *target) {
+                    ui.label(None, &format!("Hill radius: {:.0}", hill));
+                }
+                if let Some(parent) = simulation.planets.get(*target) {
+                    if ui.button(
+                        None,
+                        format!("Add moon to {}", parent.display_name(*target)).as_str(),
+                    ) {
+                        let angle = planet_params.rng.range(0.0, std::f32::consts::TAU);
+                        let moon = Planet::new(
+                            parent.position + Vec2::from_angle(angle) * planet_params.moon_distance,
+                            MOON_RADIUS,
+                            Vec2::ZERO,
+                            planet_params.moon_mass,
+                            planet_params.color,
+                        );
+                        let snapshot = snapshot_of(&simulation.planets);
+
+                        match add_moon(simulation, *target, moon) {
+                            Ok(_) => scene_state.undo.push(snapshot),
+                            Err(error) => eprintln!("Cannot add moon: {}", error),
+                        }
+                    }
+                }
+                ui.checkbox(
+                    hash!("merge_on_collision"),
+                    "Merge on collision",
+                    &mut simulation.merge_on_collision,
+                );
+                ui.checkbox(
+                    hash!("fragmentation"),
+                    "Fragment on impact",
+                    &mut simulation.fragmentation,
+                );
+                if simulation.fragmentation {
+                    ui.slider(
+                        hash!("fragment_speed"),
+                        "Fragment speed",
+                        10.0..2000.0,
+                        &mut simulation.fragment_speed,
+                    );
+                    let mut fragment_count = simulation.fragment_count as f32;
+                    ui.slider(
+                        hash!("fragment_count"),
+                        "Fragments",
+                        2.0..16.0,
+                        &mut fragment_count,
+                    );
+                    simulation.fragment_count = fragment_count as usize;
+                }
+            });
+            ui.tree_node(hash!(), "Simulation", |ui| {
+                let strength = (simulation.g / MAX_G).cbrt();
+                let mut new_strength = strength;
+                ui.slider(hash!("gravity"), "Gravity", -1.0..1.0, &mut new_strength);
+                if new_strength != strength {
+                    simulation.g = MAX_G * new_strength.powi(3);
+                }
+                ui.label(None, &format!("G: {:.2}", simulation.g));
+                ui.separator();
+                ui.slider(
+                    hash!("restitution"),
+                    "Restitution",
+                    0.0..1.0,
+                    &mut simulation.restitution,
+                );
+                ui.separator();
+                ui.slider(
+                    hash!("softening"),
+                    "Softening",
+                    0.0..50.0,
+                    &mut simulation.softening,
+                );
+                ui.slider(
+                    hash!("min_distance"),
+                    "Min distance",
+                    0.0..50.0,
+                    &mut simulation.min_distance,
+                );
+                ui.separator();
+                ui.slider(hash!("drag"), "Drag", 0.0..2.0, &mut simulation.drag);
+                ui.checkbox(
+                    hash!("speed_limit"),
+                    "Limit speed",
+                    &mut simulation.speed_limit,
+                );
+                if simulation.speed_limit {
+                    ui.slider(
+                        hash!("max_speed"),
+                        "Max speed",
+                        10.0..5000.0,
+                        &mut simulation.max_speed,
+                    );
+                    ui.label(None, "Clamping does not conserve energy");
+                }
+                ui.separator();
+                ui.checkbox(hash!("halo"), "Dark matter halo", &mut simulation.halo);
+                if simulation.halo {
+                    ui.slider(
+                        hash!("halo_scale"),
+                        "Halo core radius",
+                        1.0..1000.0,
+                        &mut simulation.halo_scale,
+                    );
+                    ui.slider(
+                        hash!("halo_strength"),
+                        "Halo speed",
+                        0.0..500.0,
+                        &mut simulation.halo_strength,
+                    );
+                    ui.label(
+                        None,
+                        &format!(
+                            "Halo center: ({:.0}, {:.0})",
+                            simulation.halo_center.x, simulation.halo_center.y
+                        ),
+                    );
+                    if ui.button(None, "Center halo on target") {
+                        if let Some(planet) = simulation.planets.get(*target) {
+                            simulation.halo_center = planet.position;
+                        }
+                    }
+                }
+                ui.separator();
+                let integrators = [Integrator::Euler, Integrator::Verlet, Integrator::Rk4];
+                let mut integrator = integrators
+                    .iter()
+                    .position(|&integrator| integrator == simulation.integrator)
+                    .unwrap_or(1);
+                ui.combo_box(
+                    hash!("integrator"),
+                    "Integrator",
+                    &["Euler", "Verlet", "RK4"],
+                    &mut integrator,
+                );
+                simulation.integrator = integrators[integrator];
+                if simulation.integrator == Integrator::Rk4 {
+                    ui.label(None, "RK4: 4 force passes per step, no Barnes-Hut");
+                }
+                let mut compare = sim_params.comparison.is_some();
+                ui.checkbox(hash!("compare"), "Compare side by side", &mut compare);
+                if !compare {
+                    sim_params.comparison = None;
+                } else if sim_params.comparison.is_none() {
+                    let other = if simulation.integrator == Integrator::Euler {
+                        Integrator::Verlet
+                    } else {
+                        Integrator::Euler
+                    };
+                    sim_params.compare(simulation, other);
+                }
+                if let Some(comparison) = &mut sim_params.comparison {
+                    let mut other = integrators
+                        .iter()
+                        .position(|&integrator| integrator == comparison.integrator)
+                        .unwrap_or(0);
+                    ui.combo_box(
+                        hash!("comparison_integrator"),
+                        "Right side",
+                        &["Euler", "Verlet", "RK4"],
+                        &mut other,
+                    );
+                    comparison.integrator = integrators[other];
+                    if ui.button(None, "Restart comparison") {
+                        sim_params.compare(simulation, integrators[other]);
+                    }
+                }
+                ui.checkbox(
+                    hash!("barnes_hut"),
+                    "Barnes-Hut",
+                    &mut simulation.barnes_hut,
+                );
+                ui.slider(hash!("theta"), "Theta", 0.0..2.0, &mut simulation.theta);
+                ui.slider(
+                    hash!("cell_size"),
+                    "Collision cell",
+                    4.0..256.0,
+                    &mut simulation.cell_size,
+                );
+                ui.checkbox(
+                    hash!("double_precision"),
+                    "Double precision (Verlet)",
+                    &mut simulation.double_precision,
+                );
+                ui.separator();
+                let mut trail_length = simulation.trail_length as f32;
+                ui.slider(
+                    hash!("trail_length"),
+                    "Trail length",
+                    0.0..10000.0,
+                    &mut trail_length,
+                );
+                simulation.trail_length = trail_length as usize;
+                let mut trail_interval = simulation.trail_interval as f32;
+                ui.slider(
+                    hash!("trail_interval"),
+                    "Trail interval",
+                    1.0..20.0,
+                    &mut trail_interval,
+                );
+                simulation.trail_interval = (trail_interval as usize).max(1);
+
+                let mut time_scale = sim_params.time_scale.log10();
+                ui.slider(
+                    hash!("time_scale"),
+                    "Time scale",
+                    MIN_TIME_SCALE.log10()..MAX_TIME_SCALE.log10(),
+                    &mut time_scale,
+                );
+                sim_params.time_scale = 10f32.powf(time_scale);
+                ui.label(None, &format!("{:.2}x", sim_params.time_scale));
+                ui.checkbox(
+                    hash!("adaptive"),
+                    "Adaptive timestep",
+                    &mut sim_params.adaptive,
+                );
+                if sim_params.adaptive {
+                    ui.slider(
+                        hash!("accuracy"),
+                        "Accuracy",
+                        0.005..0.2,
+                        &mut sim_params.accuracy,
+                    );
+                }
+                ui.checkbox(
+                    hash!("pause_on_collision"),
+                    "Pause on collision",
+                    &mut sim_params.pause_on_collision,
+                );
+                if sim_params.pause_on_collision {
+                    ui.slider(
+                        hash!("pause_speed"),
+                        "Min impact speed",
+                        0.0..1000.0,
+                        &mut sim_params.pause_speed,
+                    );
+                }
+                ui.separator();
+                let boundaries = [Boundary::None, Boundary::Walls, Boundary::Wrap];
+                let mut boundary = boundaries
+                    .iter()
+                    .position(|&boundary| boundary == simulation.boundary)
+                    .unwrap_or(0);
+                ui.combo_box(
+                    hash!("boundary"),
+                    "Boundary",
+                    &["None", "Walls", "Wrap"],
+                    &mut boundary,
+                );
+                simulation.boundary = boundaries[boundary];
+
+                let center = simulation.bounds.center();
+                let mut size = simulation.bounds.size();
+                ui.slider(hash!("bounds_width"), "Width", 100.0..5000.0, &mut size.x);
+                ui.slider(hash!("bounds_height"), "Height", 100.0..5000.0, &mut size.y);
+                simulation.bounds = Rect::new(
+                    center.x - size.x / 2.0,
+                    center.y - size.y / 2.0,
+                    size.x,
+                    size.y,
+                );
+            });
+            ui.tree_node(hash!(), "View", |ui| {
+                ui.checkbox(
+                    hash!("show_velocity"),
+                    "Velocity arrows",
+                    &mut view_params.show_velocity,
+                );
+                ui.slider(
+                    hash!("velocity_scale"),
+                    "Arrow scale",
+                    0.1..10.0,
+                    &mut view_params.velocity_scale,
+                );
+                ui.checkbox(
+                    hash!("show_acceleration"),
+                    "Acceleration arrows",
+                    &mut view_params.show_acceleration,
+                );
+                ui.slider(
+                    hash!("acceleration_scale"),
+                    "Acceleration scale",
+                    0.01..1.0,
+                    &mut view_params.acceleration_scale,
+                );
+                ui.slider(
+                    hash!("trail_opacity"),
+                    "Trail opacity",
+                    0.0..1.0,
+                    &mut view_params.trail_opacity,
+                );
+                ui.checkbox(
+                    hash!("speed_colored_trails"),
+                    "Speed-colored trails",
+                    &mut view_params.speed_colored_trails,
+                );
+                let body_colors = [BodyColor::User, BodyColor::Speed];
+                let mut body_color = body_colors
+                    .iter()
+                    .position(|&body_color| body_color == view_params.body_color)
+                    .unwrap_or(0);
+                ui.combo_box(
+                    hash!("body_color"),
+                    "Body color",
+                    &["User color", "Speed color"],
+                    &mut body_color,
+                );
+                view_params.body_color = body_colors[body_color];
+                if ui.button(None, "Clear trails") {
+                    simulation.clear_trails();
+                }
+                ui.separator();
+                if ui.button(None, "Zoom to fit") {
+                    view_params.fit_requested = true;
+                }
+                ui.separator();
+                ui.checkbox(hash!("show_grid"), "Show grid", &mut view_params.show_grid);
+                ui.checkbox(
+                    hash!("show_field"),
+                    "Field heatmap",
+                    &mut view_params.show_field,
+                );
+                if view_params.show_field {
+                    ui.slider(
+                        hash!("field_opacity"),
+                        "Heatmap opacity",
+                        0.0..1.0,
+                        &mut view_params.field_opacity,
+                    );
+                }
+                ui.checkbox(
+                    hash!("show_minimap"),
+                    "Minimap",
+                    &mut view_params.show_minimap,
+                );
+                ui.separator();
+                ui.checkbox(hash!("muted"), "Mute", &mut view_params.muted);
+                ui.slider(hash!("volume"), "Volume", 0.0..1.0, &mut view_params.volume);
+                ui.checkbox(
+                    hash!("show_starfield"),
+                    "Starfield",
+                    &mut view_params.show_starfield,
+                );
+                ui.same_line(0.0);
+                ui.checkbox(hash!("parallax"), "Parallax", &mut view_params.parallax);
+                let mut star_count = view_params.star_count as f32;
+                ui.slider(
+                    hash!("star_count"),
+                    "Star density",
+                    0.0..2000.0,
+                    &mut star_count,
+                );
+                view_params.star_count = star_count as usize;
+                ui.separator();
+                ui.label(None, "Background");
+                for (name, color) in [
+                    ("Black", BLACK),
+                    ("Dark blue", Color::new(0.02, 0.03, 0.12, 1.0)),
+                    ("White", WHITE),
+                ] {
+                    ui.same_line(0.0);
+                    if ui.button(None, name) {
+                        view_params.background = color;
+                    }
+                }
+                ui.checkbox(
+                    hash!("show_labels"),
+                    "Show labels",
+                    &mut view_params.show_labels,
+                );
+                ui.checkbox(hash!("label_name"), "Name", &mut view_params.label_name);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("label_mass"), "Mass", &mut view_params.label_mass);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("label_speed"), "Speed", &mut view_params.label_speed);
+                ui.separator();
+                ui.checkbox(
+                    hash!("show_impacts"),
+                    "Warn of collisions",
+                    &mut view_params.show_impacts,
+                );
+                if view_params.show_impacts {
+                    ui.slider(
+                        hash!("impact_horizon"),
+                        "Lookahead",
+                        0.5..10.0,
+                        &mut view_params.impact_horizon,
+                    );
+                }
+                ui.checkbox(
+                    hash!("show_center_of_mass"),
+                    "Show center of mass",
+                    &mut view_params.show_center_of_mass,
+                );
+                ui.checkbox(
+                    hash!("follow_center_of_mass"),
+                    "Follow center of mass",
+                    &mut view_params.follow_center_of_mass,
+                );
+
+                let frames = [Frame::Inertial, Frame::Centered, Frame::CoMoving];
+                let mut frame = frames
+                    .iter()
+                    .position(|&frame| frame == view_params.frame)
+                    .unwrap_or(0);
+                ui.combo_box(
+                    hash!("frame"),
+                    "Frame",
+                    &["Inertial", "Center on target", "Lock to target frame"],
+                    &mut frame,
+                );
+                view_params.frame = frames[frame];
+            });
+            ui.tree_node(hash!(), "Diagnostics", |ui| {
+                ui.label(None, &format!("Energy: {:.3}", diagnostics.energy));
+                ui.label(
+                    None,
+                    &format!(
+                        "Drift: {:.3}",
+                        diagnostics.energy - diagnostics.initial_energy
+                    ),
+                );
+                ui.separator();
+                ui.label(
+                    None,
+                    &format!(
+                        "Momentum: {:.3} ({:.3}, {:.3})",
+                        diagnostics.momentum.length(),
+                        diagnostics.momentum.x,
+                        diagnostics.momentum.y
+                    ),
+                );
+                ui.label(
+                    None,
+                    &format!("Angular momentum: {:.3}", diagnostics.angular_momentum),
+                );
+                ui.separator();
+
+                let history = &diagnostics.energy_history;
+                let min = history.iter().copied().reduce(f32::min).unwrap_or(0.0);
+                let max = history.iter().copied().reduce(f32::max).unwrap_or(0.0);
+                ui.label(None, &format!("Energy max: {:.3}", max));
+
+                let mut canvas = ui.canvas();
+                let corner = canvas.request_space(GRAPH_SIZE);
+                canvas.rect(
+                    Rect::new(corner.x, corner.y, GRAPH_SIZE.x, GRAPH_SIZE.y),
+                    GRAY,
+                    None,
+                );
+
+                let point = |i: usize, energy: f32| {
+                    let t = (energy - min) / (max - min).max(f32::EPSILON);
+                    corner
+                        + vec2(
+                            i as f32 / (ENERGY_SAMPLES - 1) as f32 * GRAPH_SIZE.x,
+                            (1.0 - t) * GRAPH_SIZE.y,
+                        )
+                };
+
+                for (i, (&a, &b)) in history.iter().zip(history.iter().skip(1)).enumerate() {
+                    canvas.line(point(i, a), point(i + 1, b), Color::new(0.9, 0.4, 0.2, 1.0));
+                }
+
+                ui.label(None, &format!("Energy min: {:.3}", min));
+            });
+            ui.tree_node(hash!(), "Speeds", |ui| {
+                let tallest = diagnostics
+                    .speed_histogram
+                    .iter()
+                    .copied()
+                    .max()
+                    .unwrap_or(0);
+                ui.label(None, &format!("Count (max {})", tallest));
+
+                let mut canvas = ui.canvas();
+                let corner = canvas.request_space(HISTOGRAM_SIZE);
+                let width = HISTOGRAM_SIZE.x / HISTOGRAM_BINS as f32;
+
+                canvas.rect(
+                    Rect::new(corner.x, corner.y, HISTOGRAM_SIZE.x, HISTOGRAM_SIZE.y),
+                    GRAY,
+                    None,
+                );
+
+                for (i, &count) in diagnostics.speed_histogram.iter().enumerate() {
+                    let height = HISTOGRAM_SIZE.y * count as f32 / tallest.max(1) as f32;
+
+                    canvas.rect(
+                        Rect::new(
+                            corner.x + i as f32 * width,
+                            corner.y + HISTOGRAM_SIZE.y - height,
+                            width - 1.0,
+                            height,
+                        ),
+                        None,
+                        Color::new(0.3, 0.5, 0.9, 1.0),
+                    );
+                }
+
+                let (min, max) = diagnostics.speed_range;
+                ui.label(None, &format!("Speed: {:.1} to {:.1}", min, max));
+            });
+            ui.tree_node(hash!(), "Orbit", |ui| {
+                let planets = &simulation.planets;
+                let Some(primary) = dominant_body(planets, *target) else {
+                    ui.label(None, "No dominant body");
+                    return;
+                };
+
+                ui.label(
+                    None,
+                    &format!(
+                        "{} around {}",
+                        planets[*target].display_name(*target),
+                        planets[primary].display_name(primary)
+                    ),
+                );
+
+                match orbital_elements(&planets[*target], &planets[primary], simulation.g) {
+                    Some(elements) => {
+                        ui.label(
+                            None,
+                            &format!("Semi-major axis: {:.2}", elements.semi_major_axis),
+                        );
+                        ui.label(None, &format!("Eccentricity: {:.3}", elements.eccentricity));
+                        ui.label(
+                            None,
+                            &match elements.period {
+                                Some(period) => format!("Period: {:.2}", period),
+                                None => "Period: escape orbit".to_owned(),
+                            },
+                        );
+                    }
+                    None => ui.label(None, "No orbit"),
+                }
+
+                ui.checkbox(hash!(), "Mark apsides", &mut view_params.show_apsides);
+                if view_params.show_apsides {
+                    ui.checkbox(hash!(), "Draw ellipse", &mut view_params.show_orbit_ellipse);
+                }
+            });
+            ui.tree_node(hash!(), "Collisions", |ui| {
+                ui.label(None, &format!("Time: {:.2}", simulation.time));
+                ui.group(hash!(), vec2(280.0, 120.0), |ui| {
+                    for event in diagnostics.collisions.iter().rev() {
+                        let outcome = match &event.outcome {
+                            CollisionOutcome::Bounce => "bounced".to_owned(),
+                            CollisionOutcome::Merge(name) => format!("merged into {}", name),
+                            CollisionOutcome::Fragment(count) => {
+                                format!("shattered into {}", count)
+                            }
+                        };
+
+                        ui.label(
+                            None,
+                            &format!(
+                                "{:.2}: {} + {} at {:.1}, {}",
+                                event.time, event.first, event.second, event.speed, outcome
+                            ),
+                        );
+                    }
+                });
+
+                if ui.button(None, "Clear log") {
+                    diagnostics.collisions.clear();
+                }
+            });
+            ui.tree_node(hash!(), "Escaped", |ui| {
+                ui.checkbox(
+                    hash!("highlight_unbound"),
+                    "Gray out escaped planets",
+                    &mut view_params.highlight_unbound,
+                );
+                ui.separator();
+                let mut remove_planet_index: Option<usize> = None;
+
+                for (i, planet) in simulation.planets.iter().enumerate() {
+                    if diagnostics.is_unbound(i) {
+                        ui.label(None, &planet.display_name(i));
+                        ui.same_line(0.0);
+                        if ui.button(None, "Remove") {
+                            remove_planet_index = Some(i);
+                        }
+                    }
+                }
+
+                if let Some(index) = remove_planet_index {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    remove_planets(simulation, &mut view_params.selection, target, |i| {
+                        i == index
+                    });
+                }
+
+                if ui.button(None, "Remove all escaped") {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    remove_planets(simulation, &mut view_params.selection, target, |i| {
+                        diagnostics.is_unbound(i)
+                    });
+                }
+            });
+            ui.tree_node(hash!(), "Presets", |ui| {
+                let center = vec2(screen_width() / 2.0, screen_height() / 2.0);
+                let g = if simulation.g == 0.0 {
+                    G
+                } else {
+                    simulation.g.abs()
+                };
+                let preset = if ui.button(None, "Binary") {
+                    Some((presets::binary(center, g), g))
+                } else if ui.button(None, "Figure eight") {
+                    Some((presets::figure_eight(center, g), g))
+                } else if ui.button(None, "Solar system") {
+                    Some((presets::solar_system(center, g), g))
+                } else if ui.button(None, "Repulsive cloud") {
+                    Some((presets::repulsive_cloud(center), -g))
+                } else {
+                    None
+                };
+
+                if let Some((preset, g)) = preset {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    simulation.planets = preset;
+                    simulation.g = g;
+                    sim_params.restart_comparison(simulation);
+                    view_params.selection.clear();
+                    *target = 0;
+                }
+            });
+            ui.tree_node(hash!(), "Random", |ui| {
+                let generator = &mut scene_state.generator;
+
+                widgets::InputText::new(hash!("random_count"))
+                    .label("Count")
+                    .filter_numbers()
+                    .ui(ui, &mut generator.count);
+                widgets::InputText::new(hash!("random_seed"))
+                    .label("Seed")
+                    .filter_numbers()
+                    .ui(ui, &mut generator.seed);
+                if ui.button(None, "Generate") {
+                    match (generator.count.parse(), generator.seed.parse()) {
+                        (Ok(count), Ok(seed)) => {
+                            let bounds = Rect::new(0.0, 0.0, screen_width(), screen_height());
+
+                            scene_state.undo.push(snapshot_of(&simulation.planets));
+                            simulation.planets = presets::spawn_random(count, seed, bounds);
+                            scene_state.last_seed = Some(seed);
+                            sim_params.restart_comparison(simulation);
+                            view_params.selection.clear();
+                            *target = 0;
+                        }
+                        _ => eprintln!("Count and seed must be whole numbers"),
+                    }
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Scatter test particles") {
+                    match (generator.count.parse(), generator.seed.parse()) {
+                        (Ok(count), Ok(seed)) => {
+                            let bounds = Rect::new(0.0, 0.0, screen_width(), screen_height());
+
+                            scene_state.undo.push(snapshot_of(&simulation.planets));
+                            simulation
+                                .planets
+                                .extend(presets::scatter_test_particles(count, seed, bounds));
+                        }
+                        _ => eprintln!("Count and seed must be whole numbers"),
+                    }
+                }
+            });
+            ui.tree_node(hash!(), "Ring", |ui| {
+                let generator = &mut scene_state.generator;
+
+                let mut ring_count = generator.ring_count as f32;
+                ui.slider(hash!("ring_count"), "Count", 2.0..200.0, &mut ring_count);
+                generator.ring_count = ring_count as usize;
+                ui.slider(
+                    hash!("ring_radius"),
+                    "Radius",
+                    20.0..1000.0,
+                    &mut generator.ring_radius,
+                );
+                ui.slider(
+                    hash!("ring_mass"),
+                    "Central mass",
+                    10.0..10000.0,
+                    &mut generator.ring_mass,
+                );
+                if ui.button(None, "Generate ring") {
+                    let center = simulation.planets.get(*target).map_or(
+                        vec2(screen_width() / 2.0, screen_height() / 2.0),
+                        |planet| planet.position,
+                    );
+                    let g = if simulation.g == 0.0 { G } else { simulation.g };
+
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    simulation.planets = presets::ring(
+                        center,
+                        generator.ring_count,
+                        generator.ring_radius,
+                        generator.ring_mass,
+                        g,
+                    );
+                    simulation.g = g;
+                    sim_params.restart_comparison(simulation);
+                    view_params.selection.clear();
+                    *target = 0;
+                }
+            });
+            ui.tree_node(hash!(), "Disk", |ui| {
+                let generator = &mut scene_state.generator;
+
+                let mut disk_count = generator.disk_count as f32;
+                ui.slider(hash!("disk_count"), "Count", 10.0..5000.0, &mut disk_count);
+                generator.disk_count = disk_count as usize;
+                ui.slider(
+                    hash!("disk_radius"),
+                    "Radius",
+                    50.0..2000.0,
+                    &mut generator.disk_radius,
+                );
+                ui.slider(
+                    hash!("disk_mass"),
+                    "Central mass",
+                    10.0..50000.0,
+                    &mut generator.disk_mass,
+                );
+                ui.slider(
+                    hash!("disk_dispersion"),
+                    "Dispersion",
+                    0.0..0.5,
+                    &mut generator.disk_dispersion,
+                );
+                if ui.button(None, "Generate disk") {
+                    let center = simulation.planets.get(*target).map_or(
+                        vec2(screen_width() / 2.0, screen_height() / 2.0),
+                        |planet| planet.position,
+                    );
+                    let g = if simulation.g == 0.0 { G } else { simulation.g };
+
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    let seed = generator.seed.parse().unwrap_or(1);
+                    let mut planets = presets::disk(
+                        center,
+                        generator.disk_count,
+                        generator.disk_radius,
+                        generator.disk_mass,
+                        generator.disk_dispersion,
+                        seed,
+                        g,
+                    );
+                    if planets.len() == 1 {
+                        eprintln!(
+                            "Disk radius {} is inside the central body, no disk generated",
+                            generator.disk_radius
+                        );
+                    }
+                    if simulation.halo {
+                        simulation.halo_center = center;
+                        simulation.add_halo_rotation(&mut planets);
+                    }
+                    simulation.planets = planets;
+                    scene_state.last_seed = Some(seed);
+                    simulation.g = g;
+                    sim_params.restart_comparison(simulation);
+                    view_params.selection.clear();
+                    *target = 0;
+                }
+            });
+            ui.tree_node(hash!(), "Scene", |ui| {
+                ui.input_text(hash!("scene_path"), "Path", &mut scene_state.path);
+                ui.separator();
+                if ui.button(None, "Save") {
+                    let path = format!("scene_{}.json", scene::timestamp());
+
+                    match scene::save_scene(&simulation.planets, &path) {
+                        Ok(()) => {
+                            println!("Saved scene to {}", path);
+                            scene_state.path = path;
+                        }
+                        Err(error) => eprintln!("Failed to save scene to {}: {}", path, error),
+                    }
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Load") {
+                    if let Some(loaded) = load_scene_or_report(&scene_state.path) {
+                        scene_state.initial_planets = loaded.clone();
+                        scene_state.undo.push(snapshot_of(&simulation.planets));
+                        simulation.planets = loaded;
+                        sim_params.restart_comparison(simulation);
+                        view_params.selection.clear();
+                        *target = 0;
+                    }
+                }
+                ui.separator();
+                let generator = &mut scene_state.generator;
+                ui.input_text(
+                    hash!("position_scale"),
+                    "Position scale",
+                    &mut generator.position_scale,
+                );
+                ui.input_text(
+                    hash!("velocity_scale"),
+                    "Velocity scale",
+                    &mut generator.velocity_scale,
+                );
+                ui.input_text(hash!("mass_scale"), "Mass scale", &mut generator.mass_scale);
+                if ui.button(None, "Import states") {
+                    let scale = generator.state_scale();
+                    let imported = scale
+                        .as_ref()
+                        .ok()
+                        .and_then(|scale| import_states_or_report(&scene_state.path, scale));
+
+                    if let Err(error) = &scale {
+                        eprintln!("Failed to import states: {}", error);
+                    }
+
+                    if let Some(imported) = imported {
+                        scene_state.initial_planets = imported.clone();
+                        scene_state.undo.push(snapshot_of(&simulation.planets));
+                        simulation.planets = imported;
+                        sim_params.restart_comparison(simulation);
+                        view_params.selection.clear();
+                        *target = 0;
+                    }
+                }
+                ui.separator();
+                if let Some(recording) = &scene_state.recording {
+                    ui.label(None, &format!("Recording: {} steps", recording.len()));
+                    if ui.button(None, "Stop recording") {
+                        let path = format!("recording_{}.json", scene::timestamp());
+
+                        match scene::save_recording(recording, &path) {
+                            Ok(()) => {
+                                println!("Saved recording to {}", path);
+                                scene_state.path = path;
+                            }
+                            Err(error) => {
+                                eprintln!("Failed to save recording to {}: {}", path, error)
+                            }
+                        }
+
+                        scene_state.recording = None;
+                    }
+                } else if ui.button(None, "Record") {
+                    scene_state.recording = Some(Recording::new(simulation, scene_state.last_seed));
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Replay") {
+                    match scene::load_recording(&scene_state.path) {
+                        Ok(recording) => {
+                            println!(
+                                "Replaying {} steps from {}",
+                                recording.len(),
+                                scene_state.path
+                            );
+                            scene_state.undo.push(snapshot_of(&simulation.planets));
+                            *simulation = recording.initial.clone();
+                            sim_params.restart_comparison(simulation);
+                            scene_state.replay = Some((recording, 0));
+                            scene_state.recording = None;
+                            sim_params.paused = false;
+                            view_params.selection.clear();
+                            *target = 0;
+                        }
+                        Err(error) => eprintln!(
+                            "Failed to load recording from {}: {}",
+                            scene_state.path, error
+                        ),
+                    }
+                }
+                if let Some((recording, step)) = &scene_state.replay {
+                    ui.label(None, &format!("Replaying: {}/{}", step, recording.len()));
+                }
+                ui.separator();
+                if ui.button(None, "Screenshot") {
+                    view_params.screenshot_requested = true;
+                }
+                ui.separator();
+                if ui.button(None, "Export CSV") {
+                    if let Some(planet) = simulation.planets.get(*target) {
+                        let path = format!("trajectory_{}.csv", scene::timestamp());
+
+                        match scene::export_trajectory(planet, &path) {
+                            Ok(()) => println!("Exported trajectory to {}", path),
+                            Err(error) => {
+                                eprintln!("Failed to export trajectory to {}: {}", path, error)
+                            }
+                        }
+                    }
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Export all CSV") {
+                    let path = format!("trajectories_{}.csv", scene::timestamp());
+
+                    match scene::export_trajectories(&simulation.planets, &path) {
+                        Ok(()) => println!("Exported trajectories to {}", path),
+                        Err(error) => {
+                            eprintln!("Failed to export trajectories to {}: {}", path, error)
+                        }
+                    }
+                }
+                ui.separator();
+                ui.checkbox(
+                    hash!("reset_parameters"),
+                    "Reset parameters",
+                    &mut scene_state.reset_parameters,
+                );
+                if ui.button(None, "Reset") {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    reset_scene(scene_state, simulation, target, planet_params, sim_params);
+                    view_params.selection.clear();
+                }
+            });
+            ui.tree_node(hash!(), "Selection", |ui| {
+                let selection = &mut view_params.selection;
+                ui.label(None, &format!("Selected: {}", selection.len()));
+                ui.separator();
+                ui.slider(
+                    hash!("group_velocity_x"),
+                    "Velocity X",
+                    -100.0..100.0,
+                    &mut view_params.group_velocity.x,
+                );
+                ui.slider(
+                    hash!("group_velocity_y"),
+                    "Velocity Y",
+                    -100.0..100.0,
+                    &mut view_params.group_velocity.y,
+                );
+                if ui.button(None, "Set velocity") {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    for &i in selection.iter() {
+                        if let Some(planet) = simulation.planets.get_mut(i) {
+                            planet.velocity = view_params.group_velocity;
+                        }
+                    }
+                }
+                ui.separator();
+                ui.slider(
+                    hash!("group_offset_x"),
+                    "Offset X",
+                    -100.0..100.0,
+                    &mut view_params.group_offset.x,
+                );
+                ui.slider(
+                    hash!("group_offset_y"),
+                    "Offset Y",
+                    -100.0..100.0,
+                    &mut view_params.group_offset.y,
+                );
+                if ui.button(None, "Nudge") {
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    for &i in selection.iter() {
+                        if let Some(planet) = simulation.planets.get_mut(i) {
+                            planet.position += view_params.group_offset;
+                            planet.history.clear();
+                        }
+                    }
+                }
+                ui.separator();
+                if ui.button(None, "Delete selected") {
+                    let removed = std::mem::take(selection);
+                    scene_state.undo.push(snapshot_of(&simulation.planets));
+                    remove_planets(simulation, selection, target, |i| removed.contains(&i));
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Clear selection") {
+                    selection.clear();
+                }
+            });
+            ui.tree_node(hash!(), "Planets", |ui| {
+                let now = get_time();
+                let armed = scene_state
+                    .clear_armed_at
+                    .is_some_and(|armed_at| now - armed_at < CLEAR_CONFIRM_TIME);
+
+                if ui.button(
+                    None,
+                    if armed {
+                        "Confirm clear all"
+                    } else {
+                        "Clear all"
+                    },
+                ) {
+                    if armed {
+                        scene_state.undo.push(snapshot_of(&simulation.planets));
+                        simulation.planets.clear();
+                        sim_params.restart_comparison(simulation);
+                        view_params.selection.clear();
+                        *target = 0;
+                        scene_state.clear_armed_at = None;
+                    } else {
+                        scene_state.clear_armed_at = Some(now);
+                    }
+                }
+                ui.separator();
+
+                let mut remove_planet_index: Option<usize> = None;
+                let mut duplicate_planet_index: Option<usize> = None;
+
+                for i in (0..simulation.planets.len()).rev() {
+                    let label = simulation.planets[i].display_name(i);
+
+                    ui.tree_node(hash!("planet", i), &label, |ui| {
+                        let planet = &mut simulation.planets[i];
+                        widgets::InputText::new(hash!("planet_name", i))
+                            .label("Name")
+                            .ui(ui, &mut planet.name);
+                        ui.separator();
+                        ui.label(None, &format!("Radius: {}", planet.radius));
+                        ui.separator();
+                        ui.label(None, &format!("Mass: {}", planet.mass));
+                        ui.separator();
+                        ui.label(None, &format!("Velocity: {}", planet.velocity));
+                        ui.separator();
+                        let mut anchored = planet.anchored;
+                        ui.checkbox(hash!("anchored", i), "Anchored", &mut anchored);
+                        planet.set_anchored(anchored);
+                        ui.checkbox(hash!("frozen", i), "Frozen", &mut planet.frozen);
+                        ui.checkbox(hash!("show_trail", i), "Show trail", &mut planet.show_trail);
+                        ui.separator();
+                        if ui.button(None, "Duplicate") {
+                            duplicate_planet_index = Some(i);
+                        }
+                        ui.same_line(0.0);
+                        if ui.button(None, "Remove") {
+                            remove_planet_index = Some(i);
+                        }
+                    });
+
+                    if let Some(index) = duplicate_planet_index {
+                        let original = &simulation.planets[index];
+                        let mut duplicate = Planet {
+                            position: original.position
+                                + vec2(original.radius * 2.0 + DUPLICATE_OFFSET, 0.0),
+                            history: VecDeque::new(),
+                            ..original.clone()
+                        };
+
+                        if !duplicate.name.is_empty() {
+                            duplicate.name.push_str(" copy");
+                        }
+
+                        scene_state.undo.push(snapshot_of(&simulation.planets));
+                        simulation.planets.push(duplicate);
+                        *target = simulation.planets.len() - 1;
+                        break;
+                    }
+
+                    if let Some(index) = remove_planet_index {
+                        scene_state.undo.push(snapshot_of(&simulation.planets));
+                        remove_planets(simulation, &mut view_params.selection, target, |i| {
+                            i == index
+                        });
+                        break;
+                    }
+                }
+            });
+        });
+}
+
+fn default_scene() -> Vec<Planet> {
+    vec![
+        Planet::new(
+            vec2(screen_width() / 2.0, screen_height() / 2.0),
+            5.0,
+            vec2(-6.0, -6.0),
+            5.0,
+            Color::new(1.0, 0.0, 0.0, 1.0),
+        ),
+        Planet::new(
+            vec2(screen_width() / 2.0 + 100.0, screen_height() / 2.0),
+            10.0,
+            vec2(6.0, 6.0),
+            10.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        ),
+    ]
+}
+
+struct GeneratorParams {
+    count: String,
+    seed: String,
+    position_scale: String,
+    velocity_scale: String,
+    mass_scale: String,
+    ring_count: usize,
+    ring_radius: f32,
+    ring_mass: f32,
+    disk_count: usize,
+    disk_radius: f32,
+    disk_mass: f32,
+    disk_dispersion: f32,
+}
+
+impl GeneratorParams {
+    fn new() -> Self {
+        GeneratorParams {
+            count: "100".to_owned(),
+            seed: "1".to_owned(),
+            position_scale: "1".to_owned(),
+            velocity_scale: "1".to_owned(),
+            mass_scale: "1".to_owned(),
+            ring_count: 24,
+            ring_radius: 200.0,
+            ring_mass: 1000.0,
+            disk_count: 1000,
+            disk_radius: 400.0,
+            disk_mass: 5000.0,
+            disk_dispersion: 0.05,
+        }
+    }
+
+    fn state_scale(&self) -> Result<StateScale, String> {
+        let parse = |label: &str, value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or(format!("invalid {} scale \"{}\"", label, value))
+        };
+
+        Ok(StateScale {
+            position: parse("position", &self.position_scale)?,
+            velocity: parse("velocity", &self.velocity_scale)?,
+            mass: parse("mass", &self.mass_scale)?,
+        })
+    }
+}
+
+struct SceneState {
+    path: String,
+    initial_planets: Vec<Planet>,
+    reset_parameters: bool,
+    generator: GeneratorParams,
+    clear_armed_at: Option<f64>,
+    config: Config,
+    undo: UndoStack,
+    last_seed: Option<u64>,
+    recording: Option<Recording>,
+    replay: Option<(Recording, usize)>,
+}
+
+impl SceneState {
+    fn new(config: Config) -> Self {
+        SceneState {
+            path: String::new(),
+            initial_planets: default_scene(),
+            reset_parameters: false,
+            generator: GeneratorParams::new(),
+            clear_armed_at: None,
+            config,
+            undo: UndoStack::new(),
+            last_seed: None,
+            recording: None,
+            replay: None,
+        }
+    }
+
+    fn next_dt(&mut self, sim_params: &mut SimParams, dt: f32) -> Option<f32> {
+        let Some((recording, step)) = &self.replay else {
+            return Some(dt);
+        };
+
+        let dt = recording.dt_at(*step);
+
+        if dt.is_none() {
+            println!("Replay finished after {} steps", step);
+            self.replay = None;
+            sim_params.paused = true;
+        }
+
+        dt
+    }
+}
+
+fn advance(
+    simulation: &mut Simulation,
+    sim_params: &mut SimParams,
+    scene_state: &mut SceneState,
+) -> StepReport {
+    let report = simulation.step();
+    sim_params.step_comparison(simulation.dt);
+    sim_params.pause_on(&report);
+
+    if let Some(recording) = &mut scene_state.recording {
+        recording.record_step(simulation.dt);
+    }
+
+    if let Some((_, step)) = &mut scene_state.replay {
+        *step += 1;
+    }
+
+    report
+}
+
+fn reset_scene(
+    scene_state: &SceneState,
+    simulation: &mut Simulation,
+    target: &mut usize,
+    planet_params: &mut PlanetParams,
+    sim_params: &mut SimParams,
+) {
+    if scene_state.reset_parameters {
+        *simulation = Simulation::new(scene_state.initial_planets.clone());
+        scene_state.config.apply(simulation);
+        *planet_params = PlanetParams::new();
+        *sim_params = SimParams::new();
+    } else {
+        simulation.planets = scene_state.initial_planets.clone();
+        simulation.time = 0.0;
+        sim_params.restart_comparison(simulation);
+    }
+
+    *target = 0;
+}
+
+fn load_scene_or_report(path: &str) -> Option<Vec<Planet>> {
+    match scene::load_scene(path) {
+        Ok(planets) => {
+            println!("Loaded scene from {}", path);
+            Some(planets)
+        }
+        Err(error) => {
+            eprintln!("Failed to load scene from {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn import_states_or_report(path: &str, scale: &StateScale) -> Option<Vec<Planet>> {
+    match scene::import_states(path, scale) {
+        Ok(mut planets) => {
+            let center = vec2(screen_width() / 2.0, screen_height() / 2.0);
+
+            for planet in &mut planets {
+                planet.position += center;
+            }
+
+            println!("Imported {} states from {}", planets.len(), path);
+            Some(planets)
+        }
+        Err(error) => {
+            eprintln!("Failed to import states from {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn save_screenshot() {
+    let image = get_screen_data();
+    let path = format!("screenshot_{}.png", scene::timestamp());
+
+    std::thread::spawn(move || {
+        image.export_png(&path);
+        println!("Saved screenshot to {}", path);
+    });
+}
+
+fn scene_argument() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--scene" {
+            return args.next();
+        }
+    }
+
+    None
+}
+
+fn import_arguments() -> Option<Result<(String, StateScale), String>> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut scale = StateScale::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--import" => path = args.next(),
+            "--import-scale" => {
+                let factors: Option<Vec<f32>> = (0..3).map(|_| args.next()?.parse().ok()).collect();
+                let Some(factors) = factors else {
+                    return Some(Err(
+                        "usage: --import-scale <position> <velocity> <mass>".to_owned()
+                    ));
+                };
+
+                scale = StateScale {
+                    position: factors[0],
+                    velocity: factors[1],
+                    mass: factors[2],
+                };
+            }
+            _ => {}
+        }
+    }
+
+    path.map(|path| Ok((path, scale)))
+}
+
+fn bench_arguments() -> Option<Result<(usize, usize), String>> {
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--bench" {
+            let bodies = args.next().and_then(|arg| arg.parse().ok());
+            let steps = args.next().and_then(|arg| arg.parse().ok());
+
+            return Some(
+                bodies
+                    .zip(steps)
+                    .ok_or_else(|| "usage: --bench <n_bodies> <n_steps>".to_owned()),
+            );
+        }
+    }
+
+    None
+}
+
+fn run_bench(bodies: usize, steps: usize) {
+    let bounds = Rect::new(0.0, 0.0, BENCH_SIZE.x, BENCH_SIZE.y);
+    let mut simulation = Simulation::new(presets::spawn_random(bodies, 1, bounds));
+    simulation.barnes_hut = std::env::args().any(|arg| arg == "--barnes-hut");
+
+    let start = Instant::now();
+
+    for _ in 0..steps {
+        simulation.step();
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let threads = if cfg!(feature = "parallel") {
+        std::thread::available_parallelism().map_or(1, |threads| threads.get())
+    } else {
+        1
+    };
+
+    println!(
+        "{} bodies, {} steps{}, {} thread(s): {:.3}s ({:.1} steps/s)",
+        bodies,
+        steps,
+        if simulation.barnes_hut {
+            " (Barnes-Hut)"
+        } else {
+            ""
+        },
+        threads,
+        elapsed,
+        steps as f64 / elapsed
+    );
+}
+
+fn window_conf(config: &Config) -> Conf {
+    Conf {
+        window_title: "Planets".to_owned(),
+        window_width: config.window_width,
+        window_height: config.window_height,
+        ..Default::default()
+    }
+}
+
+fn main() {
+    match bench_arguments() {
+        Some(Ok((bodies, steps))) => run_bench(bodies, steps),
+        Some(Err(error)) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+        None => {
+            let config = Config::load();
+            macroquad::Window::from_config(window_conf(&config), run(config));
+        }
+    }
+}
+
+// Shifts a full-screen camera so the mouse maps into the comparison pane under it.
+fn pane_offset(comparing: bool, zoom: f32) -> Vec2 {
+    if !comparing {
+        return Vec2::ZERO;
+    }
+
+    let quarter = screen_width() / 4.0 / zoom;
+
+    if mouse_position().0 < screen_width() / 2.0 {
+        vec2(quarter, 0.0)
+    } else {
+        vec2(-quarter, 0.0)
+    }
+}
+
+async fn run(config: Config) {
+    let mut planet_params = PlanetParams::new();
+    let mut sim_params = SimParams::new();
+    let mut camera =
+        Camera2D::from_display_rect(Rect::new(0.0, 0.0, screen_width(), screen_height()));
+    let mut target = 0;
+    let mut simulation = Simulation::new(default_scene());
+    config.apply(&mut simulation);
+    let mut scene_state = SceneState::new(config);
+
+    if let Some(path) = scene_argument() {
+        if let Some(loaded) = load_scene_or_report(&path) {
+            simulation.planets = loaded;
+        }
+
+        scene_state.path = path;
+    }
+
+    match import_arguments() {
+        Some(Ok((path, scale))) => {
+            if let Some(imported) = import_states_or_report(&path, &scale) {
+                simulation.planets = imported;
+            }
+
+            scene_state.path = path;
+        }
+        Some(Err(error)) => eprintln!("{}", error),
+        None => {}
+    }
+
+    scene_state.initial_planets = simulation.planets.clone();
+
+    let mut view_params = ViewParams::new();
+    view_params.min_zoom = config.min_zoom;
+    view_params.max_zoom = config.max_zoom;
+    let mut accumulator = 0.0;
+    let mut diagnostics = Diagnostics::new();
+    let sounds = Sounds::load().await;
+    let mut input_state = InputState::new();
+
+    loop {
+        clear_background(view_params.background);
+
+        if !simulation.planets.is_empty() {
+            target = target.min(simulation.planets.len() - 1);
+
+            let locked = view_params.frame != Frame::Inertial;
+
+            if input_state.dragging.is_none() && (!view_params.free_camera || locked) {
+                camera.target = if view_params.follow_center_of_mass && !locked {
+                    center_of_mass(&simulation.planets)
+                } else {
+                    simulation.planets[target].position
+                } + view_params.pan;
+            }
+        } else {
+            target = 0;
+        }
+
+        let mut substeps = 0;
+        let mut before_step = None;
+        let mut merged = false;
+
+        if !sim_params.paused {
+            sim_params.pause_reason = None;
+            accumulator += get_frame_time().min(MAX_FRAME_TIME) * sim_params.time_scale;
+
+            loop {
+                let dt = sim_params.substep_dt(&simulation);
+                let Some(dt) = scene_state.next_dt(&mut sim_params, dt) else {
+                    break;
+                };
+                simulation.dt = dt;
+
+                if accumulator < simulation.dt {
+                    break;
+                }
+
+                if substeps == MAX_SUBSTEPS {
+                    accumulator = 0.0;
+                    break;
+                }
+
+                if simulation.merge_on_collision && before_step.is_none() {
+                    before_step = Some(snapshot_of(&simulation.planets));
+                }
+
+                let report = advance(&mut simulation, &mut sim_params, &mut scene_state);
+                merged |= report.merged();
+                follow_planets(&report, &mut target, &mut view_params, &mut input_state);
+                sounds.play(&report, view_params.effective_volume());
+                diagnostics.log(report);
+                accumulator -= simulation.dt;
+                substeps += 1;
+
+                if sim_params.paused {
+                    accumulator = 0.0;
+                    break;
+                }
+            }
+        }
+
+        if let Some(snapshot) = before_step.filter(|_| merged) {
+            scene_state.undo.push(snapshot);
+        }
+
+        diagnostics.update(&simulation);
+
+        let comparing = sim_params.comparison.is_some();
+        let offset = pane_offset(comparing, view_params.zoom);
+        camera.target += offset;
+
+        handle_input(
+            &mut camera,
+            &planet_params,
+            &mut sim_params,
+            &mut simulation,
+            &mut target,
+            &mut view_params,
+            &mut input_state,
+        );
+
+        camera.target -= offset;
+        view_params.pan(
+            &mut camera,
+            offset - pane_offset(comparing, view_params.zoom),
+        );
+
+        if let Some(snapshot) = input_state.snapshot.take() {
+            scene_state.undo.push(snapshot);
+        }
+
+        if input_state.undo_requested || input_state.redo_requested {
+            let restored = if input_state.undo_requested {
+                scene_state.undo.undo(&mut simulation.planets)
+            } else {
+                scene_state.undo.redo(&mut simulation.planets)
+            };
+
+            if restored {
+                sim_params.restart_comparison(&simulation);
+                view_params.selection.clear();
+                input_state.dragging = None;
+            }
+
+            input_state.undo_requested = false;
+            input_state.redo_requested = false;
+        }
+
+        if input_state.reset_requested {
+            scene_state.undo.push(snapshot_of(&simulation.planets));
+            reset_scene(
+                &scene_state,
+                &mut simulation,
+                &mut target,
+                &mut planet_params,
+                &mut sim_params,
+            );
+            view_params.selection.clear();
+            input_state.reset_requested = false;
+        }
+
+        if input_state.step_requested {
+            let dt = sim_params.dt();
+
+            if let Some(dt) = scene_state.next_dt(&mut sim_params, dt) {
+                simulation.dt = dt;
+                let before_step = simulation
+                    .merge_on_collision
+                    .then(|| snapshot_of(&simulation.planets));
+                let report = advance(&mut simulation, &mut sim_params, &mut scene_state);
+
+                if let Some(snapshot) = before_step.filter(|_| report.merged()) {
+                    scene_state.undo.push(snapshot);
+                }
+
+                follow_planets(&report, &mut target, &mut view_params, &mut input_state);
+                sounds.play(&report, view_params.effective_volume());
+                diagnostics.log(report);
+                substeps += 1;
+            }
+
+            input_state.step_requested = false;
+        }
+
+        camera.zoom = vec2(2.0 / screen_width(), -2.0 / screen_height()) * view_params.zoom;
+
+        let views = match &sim_params.comparison {
+            Some(comparison) => {
+                let half = screen_width() / 2.0;
+                let viewport = |x: f32| Some((x as i32, 0, half as i32, screen_height() as i32));
+                let left = Camera2D {
+                    zoom: vec2(camera.zoom.x * 2.0, camera.zoom.y),
+                    viewport: viewport(0.0),
+                    ..camera
+                };
+                let right = Camera2D {
+                    viewport: viewport(half),
+                    ..left
+                };
+
+                vec![(left, &simulation), (right, comparison)]
+            }
+            None => vec![(camera, &simulation)],
+        };
+
+        for (view, shown) in views {
+            set_camera(&view);
+            if view_params.show_starfield {
+                view_params.starfield.generate(view_params.star_count);
+                view_params.starfield.draw(&view, &view_params);
+            }
+
+            if view_params.show_field {
+                draw_field(&view, shown, &view_params);
+            }
+
+            if view_params.show_grid {
+                draw_grid(&view, &view_params);
+            }
+
+            if shown.boundary != Boundary::None {
+                let bounds = shown.bounds;
+                draw_rectangle_lines(
+                    bounds.x,
+                    bounds.y,
+                    bounds.w,
+                    bounds.h,
+                    2.0 / view_params.zoom,
+                    GRAY,
+                );
+            }
+
+            draw_planets(shown, target, &view_params, &diagnostics);
+        }
+
+        let pointer = Camera2D {
+            target: camera.target + pane_offset(sim_params.comparison.is_some(), view_params.zoom),
+            ..camera
+        };
+        set_camera(&pointer);
+        draw_spawn_preview(&pointer, &input_state, &planet_params, &simulation, target);
+        set_default_camera();
+
+        if let Some(comparison) = &sim_params.comparison {
+            let half = screen_width() / 2.0;
+            draw_line(
+                half,
+                0.0,
+                half,
+                screen_height(),
+                1.0,
+                view_params.foreground(0.5),
+            );
+
+            for (x, integrator) in [(0.0, simulation.integrator), (half, comparison.integrator)] {
+                draw_text(
+                    integrator_name(integrator),
+                    x + 10.0,
+                    screen_height() - 10.0,
+                    20.0,
+                    view_params.foreground(1.0),
+                );
+            }
+        }
+
+        if let Some(start) = input_state.box_start {
+            let end: Vec2 = mouse_position().into();
+            let (min, max) = (start.min(end), start.max(end));
+            draw_rectangle(min.x, min.y, max.x - min.x, max.y - min.y, SELECTION_FILL);
+            draw_rectangle_lines(
+                min.x,
+                min.y,
+                max.x - min.x,
+                max.y - min.y,
+                1.0,
+                SELECTION_COLOR,
+            );
+        }
+
+        if sim_params.paused {
+            draw_text("PAUSED", 10.0, 20.0, 20.0, view_params.foreground(1.0));
+
+            if let Some(reason) = &sim_params.pause_reason {
+                let width = measure_text(reason, None, 24, 1.0).width;
+                draw_text(
+                    reason,
+                    (screen_width() - width) / 2.0,
+                    40.0,
+                    24.0,
+                    IMPACT_COLOR,
+                );
+            }
+        }
+
+        if view_params.frame != Frame::Inertial
+            || !view_params.free_camera && !view_params.follow_center_of_mass
+        {
+            if let Some(planet) = simulation.planets.get(target) {
+                draw_text(
+                    &format!("Following {}", planet.display_name(target)),
+                    10.0,
+                    40.0,
+                    20.0,
+                    view_params.foreground(1.0),
+                );
+            }
+        }
+
+        if view_params.show_stats {
+            let mouse = pointer.screen_to_world(mouse_position().into());
+            let stats = [
+                format!("FPS: {}", get_fps()),
+                format!("Planets: {}", simulation.planets.len()),
+                format!("Substeps: {}", substeps),
+                format!("dt: {:.5}", simulation.dt),
+                format!("Mouse: ({:.1}, {:.1})", mouse.x, mouse.y),
+            ];
+
+            for (i, line) in stats.iter().enumerate() {
+                draw_text(
+                    line,
+                    screen_width() - 220.0,
+                    20.0 + i as f32 * 20.0,
+                    20.0,
+                    view_params.foreground(1.0),
+                );
+            }
+        }
+
+        if view_params.free_camera {
+            draw_text(
+                &format!("FREE CAMERA ({} to follow)", key_names(Action::LockCamera)),
+                10.0,
+                screen_height() - 10.0,
+                20.0,
+                view_params.foreground(1.0),
+            );
+        }
+
+        if view_params.show_minimap {
+            Minimap::new(&camera, &simulation.planets).draw(
+                &camera,
+                &simulation.planets,
+                &view_params,
+            );
+        }
+
+        if view_params.show_help {
+            draw_help(&view_params);
+        }
+
+        if view_params.screenshot_requested {
+            save_screenshot();
+            view_params.screenshot_requested = false;
+        }
+
+        if view_params.ui_enabled {
+            draw_ui(
+                &mut planet_params,
+                &mut sim_params,
+                &mut view_params,
+                &mut simulation,
+                &mut target,
+                &mut scene_state,
+                &mut diagnostics,
+            );
+        }
+
+        next_frame().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planets_are_drawn_at_their_simulated_position() {
+        let mut simulation = Simulation::new(vec![Planet::new(
+            vec2(100.0, 100.0),
+            10.0,
+            vec2(30.0, 0.0),
+            1.0,
+            WHITE,
+        )]);
+
+        for _ in 0..10 {
+            simulation.step();
+        }
+
+        let planet = &simulation.planets[0];
+        let trail = trail_points(&simulation, planet, None);
+
+        assert_eq!(planet.drawn_center(), planet.position);
+        assert_eq!(trail.last(), Some(&planet.position));
+    }
+
+    #[test]
+    fn single_point_trail_has_a_color() {
+        let mut view_params = ViewParams::new();
+        view_params.speed_colored_trails = true;
+
+        let colors = trail_colors(&[], 1, WHITE, f32::INFINITY, &view_params);
+        assert_eq!(colors.len(), 1);
+
+        let colors = trail_colors(&[1.0, 3.0], 3, WHITE, f32::INFINITY, &view_params);
+        assert_eq!(colors.len(), 3);
     }
 }
@@ -0,0 +1,108 @@
+use macroquad::prelude::*;
+
+use crate::sim::{map_bodies, Boundary, Planet, Simulation};
+
+#[derive(Clone, Copy)]
+pub struct PreciseState {
+    pub position: DVec2,
+    pub velocity: DVec2,
+    pub acceleration: DVec2,
+}
+
+impl PreciseState {
+    pub fn new(planet: &Planet) -> Self {
+        PreciseState {
+            position: planet.position.as_dvec2(),
+            velocity: planet.velocity.as_dvec2(),
+            acceleration: planet.acceleration.as_dvec2(),
+        }
+    }
+
+    pub fn sync(&mut self, planet: &Planet) {
+        if self.position.as_vec2() != planet.position {
+            self.position = planet.position.as_dvec2();
+        }
+
+        if self.velocity.as_vec2() != planet.velocity {
+            self.velocity = planet.velocity.as_dvec2();
+        }
+
+        if self.acceleration.as_vec2() != planet.acceleration {
+            self.acceleration = planet.acceleration.as_dvec2();
+        }
+    }
+
+    pub fn store(&self, planet: &mut Planet) {
+        planet.position = self.position.as_vec2();
+        planet.velocity = self.velocity.as_vec2();
+        planet.acceleration = self.acceleration.as_vec2();
+    }
+
+    pub fn drift(&mut self, dt: f64) {
+        self.position += self.velocity * dt + 0.5 * self.acceleration * dt * dt;
+    }
+
+    pub fn kick(&mut self, acceleration: DVec2, dt: f64) {
+        self.velocity += 0.5 * (self.acceleration + acceleration) * dt;
+        self.acceleration = acceleration;
+    }
+}
+
+pub fn accelerations(
+    states: &[PreciseState],
+    planets: &[Planet],
+    simulation: &Simulation,
+) -> Vec<DVec2> {
+    map_bodies(states, |state| {
+        states
+            .iter()
+            .zip(planets)
+            .filter(|(other, planet)| {
+                !planet.is_test_particle() && other.position != state.position
+            })
+            .fold(
+                halo_acceleration(state, simulation),
+                |acceleration, (other, planet)| {
+                    acceleration
+                        + gravity(
+                            separation(simulation, state.position, other.position),
+                            planet.mass as f64,
+                            simulation,
+                        )
+                },
+            )
+    })
+}
+
+fn halo_acceleration(state: &PreciseState, simulation: &Simulation) -> DVec2 {
+    simulation
+        .halo_acceleration(state.position.as_vec2())
+        .as_dvec2()
+}
+
+fn separation(simulation: &Simulation, from: DVec2, to: DVec2) -> DVec2 {
+    let direction = to - from;
+
+    if simulation.boundary != Boundary::Wrap {
+        return direction;
+    }
+
+    let size = dvec2(simulation.bounds.w as f64, simulation.bounds.h as f64);
+    direction - (direction / size).round() * size
+}
+
+fn gravity(direction: DVec2, mass: f64, simulation: &Simulation) -> DVec2 {
+    let distance_squared = direction.length_squared();
+
+    if distance_squared <= 0.0 {
+        return DVec2::ZERO;
+    }
+
+    let min_distance = simulation.min_distance as f64;
+    let softening = simulation.softening as f64;
+    let floored_distance_squared = distance_squared.max(min_distance * min_distance);
+    let softened_distance_squared = floored_distance_squared + softening * softening;
+
+    direction * (floored_distance_squared / distance_squared).sqrt() * simulation.g as f64 * mass
+        / softened_distance_squared.powf(1.5)
+}
@@ -0,0 +1,273 @@
+use macroquad::prelude::*;
+
+use crate::rng::Rng;
+use crate::sim::{circular_orbit_velocity, Planet, PlanetBuilder};
+
+pub fn binary(center: Vec2, g: f32) -> Vec<Planet> {
+    let mass = 100.0;
+    let separation = 200.0;
+    let speed = (g * mass / (2.0 * separation)).sqrt();
+
+    vec![
+        Planet::new(
+            center - vec2(separation / 2.0, 0.0),
+            10.0,
+            vec2(0.0, -speed),
+            mass,
+            Color::new(1.0, 0.6, 0.2, 1.0),
+        ),
+        Planet::new(
+            center + vec2(separation / 2.0, 0.0),
+            10.0,
+            vec2(0.0, speed),
+            mass,
+            Color::new(0.4, 0.7, 1.0, 1.0),
+        ),
+    ]
+}
+
+pub fn figure_eight(center: Vec2, g: f32) -> Vec<Planet> {
+    let mass = 100.0;
+    let length = 150.0;
+    let velocity_scale = (g * mass / length).sqrt();
+
+    let position = vec2(0.970_004_4, -0.243_087_53) * length;
+    let velocity = vec2(-0.932_407_4, -0.864_731_5) * velocity_scale;
+
+    vec![
+        Planet::new(
+            center + position,
+            5.0,
+            -velocity / 2.0,
+            mass,
+            Color::new(1.0, 0.3, 0.3, 1.0),
+        ),
+        Planet::new(
+            center - position,
+            5.0,
+            -velocity / 2.0,
+            mass,
+            Color::new(0.3, 1.0, 0.3, 1.0),
+        ),
+        Planet::new(center, 5.0, velocity, mass, Color::new(0.3, 0.5, 1.0, 1.0)),
+    ]
+}
+
+pub fn solar_system(center: Vec2, g: f32) -> Vec<Planet> {
+    let star_mass = 1000.0;
+    let orbits = [
+        ("Mercury", 100.0, 0.1, 4.0, Color::new(0.7, 0.7, 0.7, 1.0)),
+        ("Venus", 180.0, 0.3, 6.0, Color::new(0.9, 0.7, 0.3, 1.0)),
+        ("Earth", 260.0, 0.4, 6.0, Color::new(0.3, 0.6, 1.0, 1.0)),
+        ("Mars", 350.0, 0.2, 5.0, Color::new(1.0, 0.4, 0.2, 1.0)),
+    ];
+
+    let mut planets: Vec<Planet> = orbits
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, distance, mass, radius, color))| {
+            let direction = Vec2::from_angle(i as f32 * 2.0);
+            let speed = (g * star_mass / distance).sqrt();
+
+            PlanetBuilder::new()
+                .name(name)
+                .position(center + direction * distance)
+                .velocity(direction.perp() * speed)
+                .radius(radius)
+                .mass(mass)
+                .color(color)
+                .build()
+        })
+        .collect();
+
+    let momentum = planets.iter().fold(Vec2::ZERO, |sum, planet| {
+        sum + planet.velocity * planet.mass
+    });
+
+    let star = PlanetBuilder::new()
+        .name("Sun")
+        .position(center)
+        .velocity(-momentum / star_mass)
+        .radius(20.0)
+        .mass(star_mass)
+        .color(Color::new(1.0, 0.9, 0.4, 1.0))
+        .build();
+    planets.insert(0, star);
+
+    planets
+}
+
+pub fn ring(center: Vec2, count: usize, radius: f32, central_mass: f32, g: f32) -> Vec<Planet> {
+    let star = PlanetBuilder::new()
+        .name("Center")
+        .position(center)
+        .radius(central_mass.cbrt() * 2.0)
+        .mass(central_mass)
+        .color(Color::new(1.0, 0.9, 0.4, 1.0))
+        .build();
+
+    let mut planets = vec![star];
+
+    for i in 0..count {
+        let fraction = i as f32 / count as f32;
+        let position = center + Vec2::from_angle(fraction * std::f32::consts::TAU) * radius;
+        let velocity = circular_orbit_velocity(&planets[0], position, g);
+
+        planets.push(
+            PlanetBuilder::new()
+                .position(position)
+                .velocity(velocity)
+                .radius(3.0)
+                .mass(1.0)
+                .color(Color::new(
+                    0.4 + 0.6 * fraction,
+                    0.6,
+                    1.0 - 0.6 * fraction,
+                    1.0,
+                ))
+                .build(),
+        );
+    }
+
+    planets
+}
+
+pub fn disk(
+    center: Vec2,
+    count: usize,
+    radius: f32,
+    central_mass: f32,
+    dispersion: f32,
+    seed: u64,
+    g: f32,
+) -> Vec<Planet> {
+    let mut rng = Rng::new(seed);
+    let mut planets = ring(center, 0, radius, central_mass, g);
+    let inner = planets[0].radius * 2.0;
+    let scale_length = radius / 3.0;
+
+    if inner >= radius {
+        return planets;
+    }
+
+    // Gamma(2) radii match the r * exp(-r / h) profile of an exponential disk. Inverting its
+    // CDF over [inner, radius] keeps every sample inside the disk.
+    let cumulative = |distance: f32| {
+        let x = distance / scale_length;
+        1.0 - (1.0 + x) * (-x).exp()
+    };
+    let (low, high) = (cumulative(inner), cumulative(radius));
+
+    while planets.len() <= count {
+        let target = rng.range(low, high);
+        let (mut near, mut far) = (inner, radius);
+
+        for _ in 0..32 {
+            let middle = (near + far) / 2.0;
+
+            if cumulative(middle) < target {
+                near = middle;
+            } else {
+                far = middle;
+            }
+        }
+
+        let distance = (near + far) / 2.0;
+        let position = center + Vec2::from_angle(rng.range(0.0, std::f32::consts::TAU)) * distance;
+        let velocity = circular_orbit_velocity(&planets[0], position, g);
+        let kick = vec2(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0)) * dispersion;
+        let fraction = distance / radius;
+
+        planets.push(Planet::new(
+            position,
+            1.5,
+            velocity + kick * velocity.length(),
+            0.1,
+            Color::new(1.0 - 0.5 * fraction, 0.8, 0.5 + 0.5 * fraction, 0.9),
+        ));
+    }
+
+    planets
+}
+
+pub fn repulsive_cloud(center: Vec2) -> Vec<Planet> {
+    let mut rng = Rng::new(1);
+
+    (0..200)
+        .map(|_| {
+            let direction = Vec2::from_angle(rng.range(0.0, std::f32::consts::TAU));
+            let distance = 60.0 * rng.next_f32().sqrt();
+
+            Planet::new(
+                center + direction * distance,
+                2.0,
+                Vec2::ZERO,
+                5.0,
+                Color::new(0.9, 0.4, 1.0, 1.0),
+            )
+        })
+        .collect()
+}
+
+pub fn spawn_random(n: usize, seed: u64, bounds: Rect) -> Vec<Planet> {
+    let mut rng = Rng::new(seed);
+
+    (0..n)
+        .map(|_| {
+            let position = vec2(
+                rng.range(bounds.x, bounds.x + bounds.w),
+                rng.range(bounds.y, bounds.y + bounds.h),
+            );
+            let velocity = vec2(rng.range(-20.0, 20.0), rng.range(-20.0, 20.0));
+            let mass = rng.range(1.0, 20.0);
+            let color = Color::new(
+                rng.range(0.3, 1.0),
+                rng.range(0.3, 1.0),
+                rng.range(0.3, 1.0),
+                1.0,
+            );
+
+            Planet::new(position, 2.0 + mass.sqrt(), velocity, mass, color)
+        })
+        .collect()
+}
+
+pub fn scatter_test_particles(n: usize, seed: u64, bounds: Rect) -> Vec<Planet> {
+    let mut rng = Rng::new(seed);
+
+    (0..n)
+        .map(|_| {
+            let position = vec2(
+                rng.range(bounds.x, bounds.x + bounds.w),
+                rng.range(bounds.y, bounds.y + bounds.h),
+            );
+
+            Planet::new(
+                position,
+                1.0,
+                Vec2::ZERO,
+                0.0,
+                Color::new(0.6, 0.8, 1.0, 0.8),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_terminates_when_radius_is_inside_the_star() {
+        let planets = disk(Vec2::ZERO, 100, 50.0, 5000.0, 0.05, 1, 360.0);
+        assert_eq!(planets.len(), 1);
+
+        let planets = disk(Vec2::ZERO, 100, 400.0, 5000.0, 0.05, 1, 360.0);
+        let inner = planets[0].radius * 2.0;
+        assert_eq!(planets.len(), 101);
+        assert!(planets[1..].iter().all(|planet| {
+            let distance = planet.position.length();
+            (inner..=400.0).contains(&distance)
+        }));
+    }
+}
@@ -0,0 +1,218 @@
+use macroquad::prelude::*;
+
+use crate::sim::gravity;
+
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy)]
+pub struct Body {
+    pub position: Vec2,
+    pub mass: f32,
+}
+
+struct Node {
+    center: Vec2,
+    half_size: f32,
+    mass: f32,
+    center_of_mass: Vec2,
+    count: usize,
+    body: Option<Body>,
+    children: Option<usize>,
+}
+
+impl Node {
+    fn new(center: Vec2, half_size: f32) -> Self {
+        Node {
+            center,
+            half_size,
+            mass: 0.0,
+            center_of_mass: Vec2::ZERO,
+            count: 0,
+            body: None,
+            children: None,
+        }
+    }
+
+    fn contains(&self, position: Vec2) -> bool {
+        (position - self.center).abs().max_element() <= self.half_size
+    }
+}
+
+pub struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    pub fn new(bodies: &[Body]) -> Self {
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+
+        for body in bodies.iter().filter(|body| body.position.is_finite()) {
+            min = min.min(body.position);
+            max = max.max(body.position);
+        }
+
+        let (center, half_size) = if min.x > max.x {
+            (Vec2::ZERO, 1.0)
+        } else {
+            (
+                (min + max) / 2.0,
+                ((max - min).max_element() / 2.0).max(1.0),
+            )
+        };
+
+        let mut tree = QuadTree {
+            nodes: vec![Node::new(center, half_size)],
+        };
+
+        for body in bodies {
+            if body.position.is_finite() {
+                tree.insert(0, *body, 0);
+            }
+        }
+
+        for node in &mut tree.nodes {
+            match node.body {
+                Some(body) if node.count == 1 => node.center_of_mass = body.position,
+                _ if node.mass > 0.0 => node.center_of_mass /= node.mass,
+                _ => {}
+            }
+        }
+
+        tree
+    }
+
+    fn insert(&mut self, index: usize, body: Body, depth: usize) {
+        let node = &mut self.nodes[index];
+        node.mass += body.mass;
+        node.center_of_mass += body.position * body.mass;
+        node.count += 1;
+
+        if node.count == 1 {
+            node.body = Some(body);
+            return;
+        }
+
+        if depth >= MAX_DEPTH {
+            return;
+        }
+
+        if node.children.is_none() {
+            let existing = node.body.take();
+            self.subdivide(index);
+
+            if let Some(existing) = existing {
+                let child = self.child_for(index, existing.position);
+                self.insert(child, existing, depth + 1);
+            }
+        }
+
+        let child = self.child_for(index, body.position);
+        self.insert(child, body, depth + 1);
+    }
+
+    fn subdivide(&mut self, index: usize) {
+        let center = self.nodes[index].center;
+        let half_size = self.nodes[index].half_size / 2.0;
+        let first = self.nodes.len();
+
+        for offset in [
+            vec2(-half_size, -half_size),
+            vec2(half_size, -half_size),
+            vec2(-half_size, half_size),
+            vec2(half_size, half_size),
+        ] {
+            self.nodes.push(Node::new(center + offset, half_size));
+        }
+
+        self.nodes[index].children = Some(first);
+    }
+
+    fn child_for(&self, index: usize, position: Vec2) -> usize {
+        let node = &self.nodes[index];
+        let first = node.children.expect("node has no children");
+        let right = position.x >= node.center.x;
+        let bottom = position.y >= node.center.y;
+
+        first + right as usize + 2 * bottom as usize
+    }
+
+    pub fn acceleration(
+        &self,
+        position: Vec2,
+        g: f32,
+        softening: f32,
+        min_distance: f32,
+        theta: f32,
+    ) -> Vec2 {
+        let mut acceleration = Vec2::ZERO;
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.count == 0 || node.mass <= 0.0 {
+                continue;
+            }
+
+            let direction = node.center_of_mass - position;
+            let distance_squared = direction.length_squared();
+            let size = node.half_size * 2.0;
+
+            match node.children {
+                Some(first)
+                    if node.contains(position)
+                        || size * size >= theta * theta * distance_squared =>
+                {
+                    stack.extend(first..first + 4);
+                }
+                _ => {
+                    if distance_squared > 0.0 {
+                        acceleration += gravity(direction, node.mass, g, softening, min_distance);
+                    }
+                }
+            }
+        }
+
+        acceleration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn forces_match_brute_force() {
+        let mut rng = Rng::new(11);
+        let bodies: Vec<Body> = (0..50)
+            .map(|_| Body {
+                position: vec2(rng.range(-300.0, 300.0), rng.range(-300.0, 300.0)),
+                mass: rng.range(1.0, 50.0),
+            })
+            .collect();
+        let tree = QuadTree::new(&bodies);
+        let mut error = 0.0;
+        let mut magnitude = 0.0;
+
+        for body in &bodies {
+            let brute_force = bodies
+                .iter()
+                .filter(|other| other.position != body.position)
+                .fold(Vec2::ZERO, |acceleration, other| {
+                    acceleration
+                        + gravity(other.position - body.position, other.mass, 1.0, 1.0, 0.0)
+                });
+            let exact = tree.acceleration(body.position, 1.0, 1.0, 0.0, 0.0);
+            let approximate = tree.acceleration(body.position, 1.0, 1.0, 0.0, 0.5);
+
+            assert!((exact - brute_force).length() <= brute_force.length() * 1e-4);
+            error += (approximate - brute_force).length_squared();
+            magnitude += brute_force.length_squared();
+        }
+
+        let relative = (error / magnitude).sqrt();
+        assert!(relative < 0.02, "Barnes-Hut RMS error {}", relative);
+    }
+}
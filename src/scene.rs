@@ -0,0 +1,868 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use macroquad::prelude::*;
+
+use crate::sim::{Boundary, Integrator, Planet, PlanetBuilder, Simulation};
+
+const MAX_NESTING: usize = 64;
+
+pub fn save_scene(planets: &[Planet], path: &str) -> io::Result<()> {
+    fs::write(path, scene_to_json(planets))
+}
+
+pub fn load_scene(path: &str) -> Result<Vec<Planet>, String> {
+    let json = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let value = Parser::new(&json).parse()?;
+
+    value
+        .get("planets")
+        .and_then(Value::as_array)
+        .ok_or("missing \"planets\" array")?
+        .iter()
+        .map(planet_from_json)
+        .collect()
+}
+
+fn planet_from_json(value: &Value) -> Result<Planet, String> {
+    let number = |key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_number)
+            .ok_or(format!("missing number \"{}\"", key))
+    };
+    let numbers = |key: &str, len: usize| {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .and_then(|array| {
+                array
+                    .iter()
+                    .map(Value::as_number)
+                    .collect::<Option<Vec<_>>>()
+            })
+            .filter(|array| array.len() == len)
+            .ok_or(format!("missing {}-element array \"{}\"", len, key))
+    };
+
+    let position = numbers("position", 2)?;
+    let velocity = numbers("velocity", 2)?;
+    let color = numbers("color", 4)?;
+
+    let mut planet = Planet::new(
+        vec2(position[0], position[1]),
+        number("radius")?,
+        vec2(velocity[0], velocity[1]),
+        number("mass")?,
+        Color::new(color[0], color[1], color[2], color[3]),
+    );
+    planet.anchored = value
+        .get("anchored")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    planet.frozen = value
+        .get("frozen")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    planet.show_trail = value
+        .get("show_trail")
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+    if let Ok(acceleration) = numbers("acceleration", 2) {
+        planet.acceleration = vec2(acceleration[0], acceleration[1]);
+    }
+    planet.name = value
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned();
+
+    Ok(planet)
+}
+
+fn scene_to_json(planets: &[Planet]) -> String {
+    let mut json = String::from("{\n");
+    write_planets(&mut json, planets);
+    json.push_str("\n}\n");
+    json
+}
+
+fn write_planets(json: &mut String, planets: &[Planet]) {
+    json.push_str("  \"planets\": [");
+
+    for (i, planet) in planets.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        json.push_str("\n    {");
+        let _ = write!(json, "\n      \"name\": {},", quote(&planet.name));
+        let _ = write!(
+            json,
+            "\n      \"position\": [{}, {}],",
+            planet.position.x, planet.position.y
+        );
+        let _ = write!(
+            json,
+            "\n      \"velocity\": [{}, {}],",
+            planet.velocity.x, planet.velocity.y
+        );
+        let _ = write!(
+            json,
+            "\n      \"acceleration\": [{}, {}],",
+            planet.acceleration.x, planet.acceleration.y
+        );
+        let _ = write!(json, "\n      \"mass\": {},", planet.mass);
+        let _ = write!(json, "\n      \"radius\": {},", planet.radius);
+        let _ = write!(
+            json,
+            "\n      \"color\": [{}, {}, {}, {}],",
+            planet.color.r, planet.color.g, planet.color.b, planet.color.a
+        );
+        let _ = write!(json, "\n      \"anchored\": {},", planet.anchored);
+        let _ = write!(json, "\n      \"frozen\": {},", planet.frozen);
+        let _ = write!(json, "\n      \"show_trail\": {}", planet.show_trail);
+        json.push_str("\n    }");
+    }
+
+    if !planets.is_empty() {
+        json.push_str("\n  ");
+    }
+
+    json.push(']');
+}
+
+fn quote(string: &str) -> String {
+    let mut quoted = String::from('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+pub fn export_trajectory(planet: &Planet, path: &str) -> io::Result<()> {
+    let mut csv = String::from("step,x,y\n");
+
+    for (step, position) in planet.history.iter().enumerate() {
+        let _ = writeln!(csv, "{},{},{}", step, position.x, position.y);
+    }
+
+    fs::write(path, csv)
+}
+
+pub fn export_trajectories(planets: &[Planet], path: &str) -> io::Result<()> {
+    let mut csv = String::from("planet,step,x,y\n");
+
+    for (i, planet) in planets.iter().enumerate() {
+        for (step, position) in planet.history.iter().enumerate() {
+            let _ = writeln!(csv, "{},{},{},{}", i + 1, step, position.x, position.y);
+        }
+    }
+
+    fs::write(path, csv)
+}
+
+const IMPORT_COLORS: [Color; 6] = [
+    Color::new(1.0, 0.9, 0.4, 1.0),
+    Color::new(0.7, 0.7, 0.7, 1.0),
+    Color::new(0.9, 0.7, 0.3, 1.0),
+    Color::new(0.3, 0.6, 1.0, 1.0),
+    Color::new(1.0, 0.4, 0.2, 1.0),
+    Color::new(0.6, 0.9, 0.6, 1.0),
+];
+
+pub struct StateScale {
+    pub position: f32,
+    pub velocity: f32,
+    pub mass: f32,
+}
+
+impl StateScale {
+    pub fn new() -> Self {
+        StateScale {
+            position: 1.0,
+            velocity: 1.0,
+            mass: 1.0,
+        }
+    }
+}
+
+pub fn import_states(path: &str, scale: &StateScale) -> Result<Vec<Planet>, String> {
+    let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    states_from_text(&text, scale)
+}
+
+// One body per line as `name mass x y vx vy`, separated by commas or whitespace, with
+// `#` comments. Names may contain spaces since the last five fields are always numeric.
+fn states_from_text(text: &str, scale: &StateScale) -> Result<Vec<Planet>, String> {
+    let mut planets = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let fields: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+
+        if fields.is_empty() {
+            continue;
+        }
+
+        let error = || format!("line {}: expected name, mass, x, y, vx, vy", number + 1);
+
+        if fields.len() < 6 {
+            return Err(error());
+        }
+
+        let (name, values) = fields.split_at(fields.len() - 5);
+        let values = values
+            .iter()
+            .map(|value| value.parse::<f32>().ok().filter(|value| value.is_finite()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+
+        let mass = values[0] * scale.mass;
+        // Ephemerides use a y-up frame while the screen is y-down, so flip y to keep
+        // orbits turning the same way.
+        let position = vec2(values[1], -values[2]) * scale.position;
+        let velocity = vec2(values[3], -values[4]) * scale.velocity;
+
+        planets.push(
+            PlanetBuilder::new()
+                .name(&name.join(" "))
+                .position(position)
+                .velocity(velocity)
+                .radius((mass.max(0.0).cbrt() * 2.0).max(2.0))
+                .mass(mass)
+                .color(IMPORT_COLORS[planets.len() % IMPORT_COLORS.len()])
+                .build(),
+        );
+    }
+
+    Ok(planets)
+}
+
+pub struct Recording {
+    pub initial: Simulation,
+    pub seed: Option<u64>,
+    pub steps: Vec<(f32, usize)>,
+}
+
+impl Recording {
+    pub fn new(simulation: &mut Simulation, seed: Option<u64>) -> Self {
+        simulation.reset_precise();
+
+        let mut initial = simulation.clone();
+        initial.planets = simulation
+            .planets
+            .iter()
+            .map(Planet::without_history)
+            .collect();
+
+        Recording {
+            initial,
+            seed,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn record_step(&mut self, dt: f32) {
+        match self.steps.last_mut() {
+            Some((last, count)) if last.to_bits() == dt.to_bits() => *count += 1,
+            _ => self.steps.push((dt, 1)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.iter().map(|&(_, count)| count).sum()
+    }
+
+    pub fn dt_at(&self, step: usize) -> Option<f32> {
+        let mut remaining = step;
+
+        for &(dt, count) in &self.steps {
+            if remaining < count {
+                return Some(dt);
+            }
+
+            remaining -= count;
+        }
+
+        None
+    }
+}
+
+pub fn save_recording(recording: &Recording, path: &str) -> io::Result<()> {
+    fs::write(path, recording_to_json(recording))
+}
+
+pub fn load_recording(path: &str) -> Result<Recording, String> {
+    let json = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    recording_from_json(&json)
+}
+
+fn recording_to_json(recording: &Recording) -> String {
+    let simulation = &recording.initial;
+    let bounds = simulation.bounds;
+    let mut json = String::from("{\n");
+
+    if let Some(seed) = recording.seed {
+        let _ = writeln!(json, "  \"seed\": \"{}\",", seed);
+    }
+
+    json.push_str("  \"settings\": {");
+    let _ = write!(json, "\n    \"g\": {},", simulation.g);
+    let _ = write!(json, "\n    \"restitution\": {},", simulation.restitution);
+    let _ = write!(json, "\n    \"softening\": {},", simulation.softening);
+    let _ = write!(json, "\n    \"min_distance\": {},", simulation.min_distance);
+    let _ = write!(json, "\n    \"drag\": {},", simulation.drag);
+    let _ = write!(json, "\n    \"speed_limit\": {},", simulation.speed_limit);
+    let _ = write!(json, "\n    \"max_speed\": {},", simulation.max_speed);
+    let _ = write!(
+        json,
+        "\n    \"integrator\": {},",
+        quote(match simulation.integrator {
+            Integrator::Euler => "euler",
+            Integrator::Verlet => "verlet",
+            Integrator::Rk4 => "rk4",
+        })
+    );
+    let _ = write!(
+        json,
+        "\n    \"merge_on_collision\": {},",
+        simulation.merge_on_collision
+    );
+    let _ = write!(
+        json,
+        "\n    \"fragmentation\": {},",
+        simulation.fragmentation
+    );
+    let _ = write!(
+        json,
+        "\n    \"fragment_speed\": {},",
+        simulation.fragment_speed
+    );
+    let _ = write!(
+        json,
+        "\n    \"fragment_count\": {},",
+        simulation.fragment_count
+    );
+    let _ = write!(json, "\n    \"barnes_hut\": {},", simulation.barnes_hut);
+    let _ = write!(json, "\n    \"theta\": {},", simulation.theta);
+    let _ = write!(
+        json,
+        "\n    \"boundary\": {},",
+        quote(match simulation.boundary {
+            Boundary::None => "none",
+            Boundary::Walls => "walls",
+            Boundary::Wrap => "wrap",
+        })
+    );
+    let _ = write!(
+        json,
+        "\n    \"bounds\": [{}, {}, {}, {}],",
+        bounds.x, bounds.y, bounds.w, bounds.h
+    );
+    let _ = write!(
+        json,
+        "\n    \"double_precision\": {},",
+        simulation.double_precision
+    );
+    let _ = write!(json, "\n    \"cell_size\": {},", simulation.cell_size);
+    let _ = write!(json, "\n    \"halo\": {},", simulation.halo);
+    let _ = write!(
+        json,
+        "\n    \"halo_center\": [{}, {}],",
+        simulation.halo_center.x, simulation.halo_center.y
+    );
+    let _ = write!(json, "\n    \"halo_scale\": {},", simulation.halo_scale);
+    let _ = write!(
+        json,
+        "\n    \"halo_strength\": {}",
+        simulation.halo_strength
+    );
+    json.push_str("\n  },\n  \"steps\": [");
+
+    for (i, (dt, count)) in recording.steps.iter().enumerate() {
+        if i > 0 {
+            json.push_str(", ");
+        }
+
+        let _ = write!(json, "[{}, {}]", dt, count);
+    }
+
+    json.push_str("],\n");
+    write_planets(&mut json, &simulation.planets);
+    json.push_str("\n}\n");
+    json
+}
+
+fn recording_from_json(json: &str) -> Result<Recording, String> {
+    let value = Parser::new(json).parse()?;
+    let settings = value.get("settings").ok_or("missing \"settings\" object")?;
+    let number = |key: &str| {
+        settings
+            .get(key)
+            .and_then(Value::as_number)
+            .ok_or(format!("missing number \"{}\"", key))
+    };
+    let flag = |key: &str| {
+        settings
+            .get(key)
+            .and_then(Value::as_bool)
+            .ok_or(format!("missing boolean \"{}\"", key))
+    };
+    let name = |key: &str| {
+        settings
+            .get(key)
+            .and_then(Value::as_str)
+            .ok_or(format!("missing string \"{}\"", key))
+    };
+
+    let planets = value
+        .get("planets")
+        .and_then(Value::as_array)
+        .ok_or("missing \"planets\" array")?
+        .iter()
+        .map(planet_from_json)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut initial = Simulation::new(planets);
+    initial.g = number("g")?;
+    initial.restitution = number("restitution")?;
+    initial.softening = number("softening")?;
+    initial.min_distance = number("min_distance")?;
+    initial.drag = number("drag").unwrap_or(0.0);
+    initial.speed_limit = flag("speed_limit").unwrap_or(false);
+    initial.max_speed = number("max_speed").unwrap_or(initial.max_speed);
+    initial.integrator = match name("integrator")? {
+        "euler" => Integrator::Euler,
+        "verlet" => Integrator::Verlet,
+        "rk4" => Integrator::Rk4,
+        other => return Err(format!("unknown integrator \"{}\"", other)),
+    };
+    initial.merge_on_collision = flag("merge_on_collision")?;
+    initial.fragmentation = flag("fragmentation")?;
+    initial.fragment_speed = number("fragment_speed")?;
+    initial.fragment_count = number("fragment_count")? as usize;
+    initial.barnes_hut = flag("barnes_hut")?;
+    initial.theta = number("theta")?;
+    initial.boundary = match name("boundary")? {
+        "none" => Boundary::None,
+        "walls" => Boundary::Walls,
+        "wrap" => Boundary::Wrap,
+        other => return Err(format!("unknown boundary \"{}\"", other)),
+    };
+    let bounds = settings
+        .get("bounds")
+        .and_then(Value::as_array)
+        .and_then(|array| {
+            array
+                .iter()
+                .map(Value::as_number)
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|array| array.len() == 4)
+        .ok_or("missing 4-element array \"bounds\"")?;
+    initial.bounds = Rect::new(bounds[0], bounds[1], bounds[2], bounds[3]);
+    initial.double_precision = flag("double_precision")?;
+    initial.cell_size = number("cell_size")?;
+
+    if let Ok(halo) = flag("halo") {
+        let center = settings
+            .get("halo_center")
+            .and_then(Value::as_array)
+            .and_then(|array| {
+                array
+                    .iter()
+                    .map(Value::as_number)
+                    .collect::<Option<Vec<_>>>()
+            })
+            .filter(|array| array.len() == 2)
+            .ok_or("missing 2-element array \"halo_center\"")?;
+        initial.halo = halo;
+        initial.halo_center = vec2(center[0], center[1]);
+        initial.halo_scale = number("halo_scale")?;
+        initial.halo_strength = number("halo_strength")?;
+    }
+
+    let steps = value
+        .get("steps")
+        .and_then(Value::as_array)
+        .ok_or("missing \"steps\" array")?
+        .iter()
+        .map(|step| match step.as_array() {
+            Some([Value::Number(dt), Value::Number(count)]) => Ok((*dt, *count as usize)),
+            _ => Err("steps must be [dt, count] pairs".to_owned()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let seed = match value.get("seed").and_then(Value::as_str) {
+        Some(seed) => Some(
+            seed.parse()
+                .map_err(|_| format!("invalid seed \"{}\"", seed))?,
+        ),
+        None => None,
+    };
+
+    Ok(Recording {
+        initial,
+        seed,
+        steps,
+    })
+}
+
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+enum Value {
+    Null,
+    Bool(bool),
+    Number(f32),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn as_number(&self) -> Option<f32> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(json: &'a str) -> Self {
+        Parser {
+            chars: json.chars().peekable(),
+            depth: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<Value, String> {
+        let value = self.value()?;
+        self.skip_whitespace();
+
+        match self.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected trailing character '{}'", c)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}', found end of input", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('{' | '[') if self.depth >= MAX_NESTING => {
+                Err(format!("nesting deeper than {} levels", MAX_NESTING))
+            }
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('n') => self.literal("null", Value::Null),
+            Some(_) => self.number(),
+            None => Err("unexpected end of input".to_owned()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(fields)),
+                _ => return Err("expected ',' or '}' in object".to_owned()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err("expected ',' or ']' in array".to_owned()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('u') => {
+                        let code: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let c = u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or(format!("invalid unicode escape '{}'", code))?;
+                        string.push(c);
+                    }
+                    Some(c) => string.push(c),
+                    None => return Err("unterminated string".to_owned()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            number.push(c);
+        }
+
+        number
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}'", number))
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value, String> {
+        for expected in literal.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("invalid literal, expected '{}'", literal));
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets;
+
+    fn replay(recording: &Recording) -> Simulation {
+        let mut simulation = recording.initial.clone();
+
+        for step in 0..recording.len() {
+            simulation.dt = recording.dt_at(step).unwrap();
+            simulation.step();
+        }
+
+        simulation
+    }
+
+    #[test]
+    fn deeply_nested_json_is_rejected() {
+        let shallow = format!("{}{}", "[".repeat(MAX_NESTING), "]".repeat(MAX_NESTING));
+        assert!(Parser::new(&shallow).parse().is_ok());
+
+        let deep = "[".repeat(100_000);
+        let Err(error) = Parser::new(&deep).parse() else {
+            panic!("deeply nested input was accepted");
+        };
+        assert!(error.contains("nesting"), "{}", error);
+    }
+
+    #[test]
+    fn state_vectors_are_scaled_and_flipped() {
+        let text = "# name, mass, x, y, vx, vy\n\
+                    Sun, 1000, 0, 0, 0, 0\n\
+                    \n\
+                    Halley's Comet 0.5 1.5 -2 0.25 4  # periapsis\n";
+        let scale = StateScale {
+            position: 100.0,
+            velocity: 10.0,
+            mass: 2.0,
+        };
+
+        let planets = states_from_text(text, &scale).unwrap();
+        assert_eq!(planets.len(), 2);
+        assert_eq!(planets[0].name, "Sun");
+        assert_eq!(planets[0].mass, 2000.0);
+        assert_eq!(planets[1].name, "Halley's Comet");
+        assert_eq!(planets[1].mass, 1.0);
+        assert_eq!(planets[1].position, vec2(150.0, 200.0));
+        assert_eq!(planets[1].velocity, vec2(2.5, -40.0));
+
+        let Err(error) = states_from_text("Earth 1 2 3 four 5", &scale) else {
+            panic!("non-numeric velocity was accepted");
+        };
+        assert!(error.starts_with("line 1"), "{}", error);
+    }
+
+    #[test]
+    fn replaying_a_recording_is_bit_identical() {
+        let planets = presets::spawn_random(40, 5, Rect::new(0.0, 0.0, 400.0, 300.0));
+        let mut simulation = Simulation::new(planets);
+        simulation.merge_on_collision = true;
+
+        let mut recording = Recording::new(&mut simulation, Some(5));
+
+        for step in 0..300 {
+            simulation.dt = if step < 150 { 1.0 / 60.0 } else { 1.0 / 120.0 };
+            simulation.step();
+            recording.record_step(simulation.dt);
+        }
+
+        let loaded = recording_from_json(&recording_to_json(&recording)).unwrap();
+        assert_eq!(loaded.len(), 300);
+        assert_eq!(loaded.seed, Some(5));
+
+        let first = replay(&loaded);
+        let second = replay(&loaded);
+        let positions = |simulation: &Simulation| -> Vec<(u32, u32)> {
+            simulation
+                .planets
+                .iter()
+                .map(|planet| (planet.position.x.to_bits(), planet.position.y.to_bits()))
+                .collect()
+        };
+
+        assert_eq!(positions(&first), positions(&second));
+        assert_eq!(positions(&first), positions(&simulation));
+    }
+}
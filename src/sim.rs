@@ -0,0 +1,2028 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::precise::{self, PreciseState};
+use crate::quadtree::{Body, QuadTree};
+use crate::spatial_hash::SpatialHash;
+
+pub const DT: f32 = 1.0 / 60.0;
+pub const G: f32 = 360.0;
+pub const RESTITUTION_COEFFICIENT: f32 = 0.3;
+pub const SOFTENING: f32 = 5.0;
+const THETA: f32 = 0.5;
+const MAX_TRAIL: usize = 2000;
+const TRAIL_INTERVAL: usize = 1;
+const HALO_SCALE: f32 = 100.0;
+const HALO_STRENGTH: f32 = 100.0;
+const MAX_SPEED: f32 = 1000.0;
+const PREDICTION_STEPS: usize = 600;
+const CELL_SIZE: f32 = 32.0;
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 256;
+const FRAGMENT_SPEED: f32 = 300.0;
+const FRAGMENT_COUNT: usize = 6;
+const MIN_FRAGMENT_RADIUS: f32 = 0.5;
+const MAX_BODIES: usize = 2000;
+const MIN_DT_FRACTION: f32 = 0.001;
+const CONTACT_TOLERANCE: f32 = 0.999;
+const BOUNDS: Rect = Rect {
+    x: 0.0,
+    y: 0.0,
+    w: 800.0,
+    h: 600.0,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    None,
+    Walls,
+    Wrap,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    Euler,
+    Verlet,
+    Rk4,
+}
+
+#[derive(Clone)]
+pub struct Planet {
+    pub position: Vec2,
+    pub radius: f32,
+    pub velocity: Vec2,
+    pub acceleration: Vec2,
+    pub mass: f32,
+    pub history: VecDeque<Vec2>,
+    pub color: Color,
+    pub anchored: bool,
+    pub frozen: bool,
+    pub show_trail: bool,
+    pub name: String,
+}
+
+impl Planet {
+    pub fn new(position: Vec2, radius: f32, velocity: Vec2, mass: f32, color: Color) -> Self {
+        Planet {
+            position,
+            radius,
+            velocity,
+            acceleration: Vec2::ZERO,
+            mass,
+            history: VecDeque::new(),
+            color,
+            anchored: false,
+            frozen: false,
+            show_trail: true,
+            name: String::new(),
+        }
+    }
+
+    fn drift(&mut self, dt: f32) {
+        if self.is_fixed() {
+            return;
+        }
+
+        self.position += self.velocity * dt + 0.5 * self.acceleration * dt * dt;
+    }
+
+    fn kick(&mut self, acceleration: Vec2, dt: f32) {
+        if self.is_fixed() {
+            return;
+        }
+
+        self.velocity += 0.5 * (self.acceleration + acceleration) * dt;
+        self.acceleration = acceleration;
+    }
+
+    fn advance(&mut self, acceleration: Vec2, integrator: Integrator, dt: f32) {
+        match integrator {
+            Integrator::Euler => {
+                self.velocity += self.acceleration * dt;
+                self.acceleration = acceleration;
+            }
+            Integrator::Verlet => self.kick(acceleration, dt),
+            Integrator::Rk4 => self.acceleration = acceleration,
+        }
+    }
+
+    pub fn display_name(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("Planet {}", index + 1)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    pub fn is_test_particle(&self) -> bool {
+        self.mass <= 0.0
+    }
+
+    pub fn without_history(&self) -> Planet {
+        Planet {
+            history: VecDeque::new(),
+            name: self.name.clone(),
+            ..*self
+        }
+    }
+
+    fn collide(&mut self, other: &mut Planet, simulation: &Simulation) {
+        let both_test_particles = self.is_test_particle() && other.is_test_particle();
+
+        if self.is_fixed() && other.is_fixed() || both_test_particles {
+            return;
+        }
+
+        let direction = simulation.separation(self.position, other.position);
+        let distance = direction.length();
+        let overlap = self.radius + other.radius - distance;
+
+        if distance <= 0.0 || overlap < 0.0 {
+            return;
+        }
+
+        let normal = direction / distance;
+        // Fraction of the response taken by `self`: the other body's share of the
+        // total mass, with fixed bodies taking none of it.
+        let share = if self.is_fixed() {
+            0.0
+        } else if other.is_fixed() {
+            1.0
+        } else {
+            other.mass / (self.mass + other.mass)
+        };
+        let approach = (self.velocity - other.velocity).dot(normal);
+
+        if approach > 0.0 {
+            let change = (1.0 + simulation.restitution) * approach;
+            self.velocity -= change * share * normal;
+            other.velocity += change * (1.0 - share) * normal;
+        }
+
+        self.position -= overlap * share * normal;
+        other.position += overlap * (1.0 - share) * normal;
+    }
+
+    fn record(&mut self, trail_length: usize) {
+        self.history.push_back(self.position);
+
+        while self.history.len() > trail_length {
+            self.history.pop_front();
+        }
+    }
+
+    fn overlaps(&self, other: &Planet, simulation: &Simulation) -> bool {
+        simulation
+            .separation(self.position, other.position)
+            .length_squared()
+            <= (self.radius + other.radius).powi(2)
+    }
+
+    fn absorb(&mut self, other: &Planet) {
+        let mass = self.mass + other.mass;
+        let self_weight = self.mass / mass;
+        let other_weight = other.mass / mass;
+
+        self.position = match (self.anchored, other.anchored) {
+            (true, _) => self.position,
+            (false, true) => other.position,
+            _ => self.position * self_weight + other.position * other_weight,
+        };
+        self.velocity = self.velocity * self_weight + other.velocity * other_weight;
+        self.acceleration = self.acceleration * self_weight + other.acceleration * other_weight;
+        self.radius = (self.radius.powi(3) + other.radius.powi(3)).cbrt();
+        self.color = Color::new(
+            self.color.r * self_weight + other.color.r * other_weight,
+            self.color.g * self_weight + other.color.g * other_weight,
+            self.color.b * self_weight + other.color.b * other_weight,
+            self.color.a * self_weight + other.color.a * other_weight,
+        );
+        self.mass = mass;
+
+        if self.anchored || other.anchored {
+            self.anchored = true;
+            self.velocity = Vec2::ZERO;
+            self.acceleration = Vec2::ZERO;
+        }
+    }
+
+    fn wrap_around(&mut self, bounds: Rect) {
+        if self.is_fixed() {
+            return;
+        }
+
+        let min = vec2(bounds.x, bounds.y);
+        let size = vec2(bounds.w, bounds.h);
+
+        let offset = self.position - min;
+
+        self.position = min + vec2(offset.x.rem_euclid(size.x), offset.y.rem_euclid(size.y));
+    }
+
+    fn bounce_off_walls(&mut self, bounds: Rect, restitution: f32) {
+        if self.is_fixed() {
+            return;
+        }
+
+        let min = vec2(bounds.x, bounds.y) + self.radius;
+        let max = vec2(bounds.x + bounds.w, bounds.y + bounds.h) - self.radius;
+
+        for axis in 0..2 {
+            if self.position[axis] < min[axis] {
+                self.position[axis] = min[axis].min(max[axis]);
+                self.velocity[axis] = self.velocity[axis].abs() * restitution;
+            } else if self.position[axis] > max[axis] {
+                self.position[axis] = max[axis].max(min[axis]);
+                self.velocity[axis] = -self.velocity[axis].abs() * restitution;
+            }
+        }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.anchored || self.frozen
+    }
+
+    pub fn set_anchored(&mut self, anchored: bool) {
+        if anchored && !self.anchored {
+            self.velocity = Vec2::ZERO;
+            self.acceleration = Vec2::ZERO;
+        }
+
+        self.anchored = anchored;
+    }
+}
+
+pub struct PlanetBuilder {
+    position: Vec2,
+    velocity: Vec2,
+    radius: f32,
+    mass: f32,
+    color: Color,
+    name: String,
+}
+
+impl PlanetBuilder {
+    pub fn new() -> Self {
+        PlanetBuilder {
+            position: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            radius: 10.0,
+            mass: 10.0,
+            color: WHITE,
+            name: String::new(),
+        }
+    }
+
+    pub fn position(mut self, position: Vec2) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn velocity(mut self, velocity: Vec2) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn mass(mut self, mass: f32) -> Self {
+        self.mass = mass;
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn build(self) -> Planet {
+        let mut planet = Planet::new(
+            self.position,
+            self.radius,
+            self.velocity,
+            self.mass,
+            self.color,
+        );
+        planet.name = self.name;
+        planet
+    }
+}
+
+// Softening smooths the force everywhere; the distance floor leaves it exact
+// until bodies get closer than `min_distance`, then holds it at that value.
+pub fn gravity(direction: Vec2, mass: f32, g: f32, softening: f32, min_distance: f32) -> Vec2 {
+    let distance_squared = direction.length_squared();
+
+    if distance_squared <= 0.0 {
+        return Vec2::ZERO;
+    }
+
+    let floored_distance_squared = distance_squared.max(min_distance * min_distance);
+    let softened_distance_squared = floored_distance_squared + softening * softening;
+
+    direction * (floored_distance_squared / distance_squared).sqrt() * g * mass
+        / softened_distance_squared.powf(1.5)
+}
+
+fn acceleration_field(
+    positions: &[Vec2],
+    planets: &[Planet],
+    simulation: &Simulation,
+) -> Vec<Vec2> {
+    map_bodies(positions, |&position| {
+        positions
+            .iter()
+            .zip(planets)
+            .filter(|&(&other, planet)| !planet.is_test_particle() && other != position)
+            .fold(
+                simulation.halo_acceleration(position),
+                |acceleration, (&other, planet)| {
+                    acceleration
+                        + gravity(
+                            simulation.separation(position, other),
+                            planet.mass,
+                            simulation.g,
+                            simulation.softening,
+                            simulation.min_distance,
+                        )
+                },
+            )
+    })
+}
+
+fn acceleration_at(position: Vec2, planets: &[Planet], simulation: &Simulation) -> Vec2 {
+    planets
+        .iter()
+        .filter(|planet| !planet.is_test_particle() && planet.position != position)
+        .fold(
+            simulation.halo_acceleration(position),
+            |acceleration, planet| {
+                acceleration
+                    + gravity(
+                        simulation.separation(position, planet.position),
+                        planet.mass,
+                        simulation.g,
+                        simulation.softening,
+                        simulation.min_distance,
+                    )
+            },
+        )
+}
+
+// Scoped std threads over contiguous chunks rather than rayon: the per-body work is
+// uniform, so static chunking loses nothing and the crate stays free of dependencies.
+pub fn map_bodies<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+
+    #[cfg(feature = "parallel")]
+    if items.len() >= PARALLEL_THRESHOLD && threads > 1 {
+        let chunk_size = items.len().div_ceil(threads);
+
+        return std::thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("force thread panicked"))
+                .collect()
+        });
+    }
+
+    items.iter().map(f).collect()
+}
+
+#[derive(Clone)]
+pub struct CollisionEvent {
+    pub time: f32,
+    pub first: String,
+    pub second: String,
+    pub speed: f32,
+    pub outcome: CollisionOutcome,
+}
+
+#[derive(Clone)]
+pub enum CollisionOutcome {
+    Bounce,
+    Merge(String),
+    Fragment(usize),
+}
+
+#[derive(Default)]
+pub struct StepReport {
+    pub collisions: Vec<CollisionEvent>,
+    pub removed_non_finite: usize,
+    pub reset_non_finite: usize,
+    // New index of every planet from before the step, when merges or removals shifted them.
+    // Merged planets map to the body that absorbed them.
+    pub remap: Option<Vec<Option<usize>>>,
+}
+
+impl StepReport {
+    pub fn is_empty(&self) -> bool {
+        self.collisions.is_empty() && self.removed_non_finite == 0 && self.reset_non_finite == 0
+    }
+
+    pub fn merged(&self) -> bool {
+        self.collisions
+            .iter()
+            .any(|event| matches!(event.outcome, CollisionOutcome::Merge(_)))
+    }
+
+    pub fn new_index(&self, index: usize) -> Option<usize> {
+        match &self.remap {
+            Some(remap) => remap.get(index).copied().flatten(),
+            None => Some(index),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Simulation {
+    pub planets: Vec<Planet>,
+    pub g: f32,
+    pub restitution: f32,
+    pub softening: f32,
+    pub min_distance: f32,
+    pub drag: f32,
+    pub speed_limit: bool,
+    pub max_speed: f32,
+    pub dt: f32,
+    pub integrator: Integrator,
+    pub merge_on_collision: bool,
+    pub fragmentation: bool,
+    pub fragment_speed: f32,
+    pub fragment_count: usize,
+    pub barnes_hut: bool,
+    pub theta: f32,
+    pub trail_length: usize,
+    pub trail_interval: usize,
+    pub boundary: Boundary,
+    pub bounds: Rect,
+    pub time: f32,
+    pub double_precision: bool,
+    pub cell_size: f32,
+    pub halo: bool,
+    pub halo_center: Vec2,
+    pub halo_scale: f32,
+    pub halo_strength: f32,
+    precise: Vec<PreciseState>,
+    step_count: usize,
+}
+
+impl Simulation {
+    pub fn new(planets: Vec<Planet>) -> Self {
+        Simulation {
+            planets,
+            g: G,
+            restitution: RESTITUTION_COEFFICIENT,
+            softening: SOFTENING,
+            min_distance: 0.0,
+            drag: 0.0,
+            speed_limit: false,
+            max_speed: MAX_SPEED,
+            dt: DT,
+            integrator: Integrator::Verlet,
+            merge_on_collision: false,
+            fragmentation: false,
+            fragment_speed: FRAGMENT_SPEED,
+            fragment_count: FRAGMENT_COUNT,
+            barnes_hut: false,
+            theta: THETA,
+            trail_length: MAX_TRAIL,
+            trail_interval: TRAIL_INTERVAL,
+            boundary: Boundary::None,
+            bounds: BOUNDS,
+            time: 0.0,
+            double_precision: false,
+            cell_size: CELL_SIZE,
+            halo: false,
+            halo_center: BOUNDS.center(),
+            halo_scale: HALO_SCALE,
+            halo_strength: HALO_STRENGTH,
+            precise: Vec::new(),
+            step_count: 0,
+        }
+    }
+
+    pub fn step(&mut self) -> StepReport {
+        let mut planets = std::mem::take(&mut self.planets);
+        let mut precise = std::mem::take(&mut self.precise);
+        let previous: Vec<Vec2> = planets.iter().map(|planet| planet.position).collect();
+
+        if !self.double_precision || self.integrator != Integrator::Verlet {
+            precise.clear();
+        } else if precise.len() != planets.len() {
+            precise = planets.iter().map(PreciseState::new).collect();
+        } else {
+            for (state, planet) in precise.iter_mut().zip(&planets) {
+                state.sync(planet);
+            }
+        }
+
+        let runge_kutta = (self.integrator == Integrator::Rk4).then(|| self.runge_kutta(&planets));
+
+        for (i, planet) in planets.iter_mut().enumerate() {
+            match (precise.get_mut(i), &runge_kutta) {
+                _ if planet.is_fixed() => {}
+                (Some(state), _) => {
+                    state.drift(self.dt as f64);
+                    planet.position = state.position.as_vec2();
+                }
+                (None, Some(states)) => (planet.position, planet.velocity) = states[i],
+                (None, None) if self.integrator == Integrator::Euler => {
+                    planet.position += planet.velocity * self.dt;
+                }
+                (None, None) => planet.drift(self.dt),
+            }
+
+            self.apply_boundary(planet);
+        }
+
+        let pairs = self.candidate_pairs(&planets, &previous);
+        let contacts = self.sweep_collisions(&mut planets, &previous, &pairs);
+        let mut shattered = Vec::new();
+
+        if self.fragmentation {
+            shattered = self.fragment_planets(&mut planets, &pairs);
+
+            if !precise.is_empty() {
+                precise.extend(planets[precise.len()..].iter().map(PreciseState::new));
+            }
+        }
+
+        // A contact that shattered reports only the fragment outcome.
+        let mut events: Vec<CollisionEvent> = contacts
+            .into_iter()
+            .filter(|(pair, _)| !shattered.iter().any(|(other, _)| other == pair))
+            .map(|(_, event)| event)
+            .collect();
+        events.extend(shattered.iter().map(|(_, event)| event.clone()));
+
+        if !self.merge_on_collision {
+            for &(i, j) in &pairs {
+                if shattered.iter().any(|(pair, _)| *pair == (i, j)) {
+                    continue;
+                }
+
+                let (head, tail) = planets.split_at_mut(j);
+                head[i].collide(&mut tail[0], self);
+            }
+        }
+
+        let tree = (self.barnes_hut && self.boundary != Boundary::Wrap).then(|| {
+            let bodies: Vec<Body> = planets
+                .iter()
+                .filter(|planet| !planet.is_test_particle())
+                .map(|planet| Body {
+                    position: planet.position,
+                    mass: planet.mass,
+                })
+                .collect();
+
+            QuadTree::new(&bodies)
+        });
+
+        let planets_clone: Vec<Planet> = planets.iter().map(Planet::without_history).collect();
+        let sample_trail = self.step_count.is_multiple_of(self.trail_interval.max(1));
+
+        if precise.is_empty() {
+            let accelerations = map_bodies(&planets_clone, |planet| {
+                if planet.is_fixed() {
+                    return Vec2::ZERO;
+                }
+
+                match &tree {
+                    Some(tree) => {
+                        tree.acceleration(
+                            planet.position,
+                            self.g,
+                            self.softening,
+                            self.min_distance,
+                            self.theta,
+                        ) + self.halo_acceleration(planet.position)
+                    }
+                    None => acceleration_at(planet.position, &planets_clone, self),
+                }
+            });
+
+            for (planet, acceleration) in planets.iter_mut().zip(accelerations) {
+                if planet.is_fixed() {
+                    continue;
+                }
+
+                planet.advance(acceleration, self.integrator, self.dt);
+                if sample_trail {
+                    planet.record(self.trail_length);
+                }
+            }
+        } else {
+            for (state, planet) in precise.iter_mut().zip(&planets) {
+                state.sync(planet);
+            }
+
+            let accelerations = precise::accelerations(&precise, &planets, self);
+
+            for ((state, planet), acceleration) in precise
+                .iter_mut()
+                .zip(planets.iter_mut())
+                .zip(accelerations)
+            {
+                if planet.is_fixed() {
+                    continue;
+                }
+
+                state.kick(acceleration, self.dt as f64);
+                state.store(planet);
+                if sample_trail {
+                    planet.record(self.trail_length);
+                }
+            }
+        }
+
+        if self.drag > 0.0 {
+            let damping = (1.0 - self.drag * self.dt).max(0.0);
+
+            for (i, planet) in planets.iter_mut().enumerate() {
+                if planet.is_fixed() {
+                    continue;
+                }
+
+                planet.velocity *= damping;
+
+                if let Some(state) = precise.get_mut(i) {
+                    state.velocity *= damping as f64;
+                }
+            }
+        }
+
+        // A safety net for casual sandboxes: clamping removes kinetic energy, so energy is
+        // no longer conserved while the limit is active.
+        if self.speed_limit {
+            for (i, planet) in planets.iter_mut().enumerate() {
+                if planet.is_fixed() || planet.velocity.length() <= self.max_speed {
+                    continue;
+                }
+
+                planet.velocity = planet.velocity.clamp_length_max(self.max_speed);
+
+                if let Some(state) = precise.get_mut(i) {
+                    state.velocity = planet.velocity.as_dvec2();
+                }
+            }
+        }
+
+        let mut remap = None;
+
+        if self.merge_on_collision {
+            let (merges, merge_remap) = merge_planets(&mut planets, &pairs, self);
+            events.extend(merges);
+            remap = merge_remap;
+        }
+
+        let (removed_non_finite, reset_non_finite) = recover_non_finite(&mut planets, &mut remap);
+
+        self.planets = planets;
+        self.precise = precise;
+        self.time += self.dt;
+        self.step_count += 1;
+
+        StepReport {
+            collisions: events,
+            removed_non_finite,
+            reset_non_finite,
+            remap,
+        }
+    }
+
+    pub fn adaptive_dt(&self, max_dt: f32, accuracy: f32) -> f32 {
+        let planets = &self.planets;
+        let mut timescale = f32::INFINITY;
+
+        for i in 0..planets.len() {
+            for j in (i + 1)..planets.len() {
+                let (a, b) = (&planets[i], &planets[j]);
+
+                if a.is_test_particle() && b.is_test_particle() {
+                    continue;
+                }
+
+                let distance_squared = self.separation(a.position, b.position).length_squared()
+                    + self.softening * self.softening;
+                let distance = distance_squared.sqrt();
+                let speed = (a.velocity - b.velocity).length();
+                let attraction = self.g.abs() * (a.mass.max(0.0) + b.mass.max(0.0));
+
+                if speed > 0.0 {
+                    timescale = timescale.min(distance / speed);
+                }
+
+                if attraction > 0.0 {
+                    timescale = timescale.min((distance_squared * distance / attraction).sqrt());
+                }
+            }
+        }
+
+        (accuracy * timescale).clamp(max_dt * MIN_DT_FRACTION, max_dt)
+    }
+
+    pub fn reset_precise(&mut self) {
+        self.precise.clear();
+    }
+
+    pub fn clear_trails(&mut self) {
+        for planet in &mut self.planets {
+            planet.history.clear();
+        }
+    }
+
+    fn runge_kutta(&self, planets: &[Planet]) -> Vec<(Vec2, Vec2)> {
+        let dt = self.dt;
+        let mask = |values: Vec<Vec2>| -> Vec<Vec2> {
+            values
+                .into_iter()
+                .zip(planets)
+                .map(|(value, planet)| if planet.is_fixed() { Vec2::ZERO } else { value })
+                .collect()
+        };
+        let offset = |base: &[Vec2], delta: &[Vec2], scale: f32| -> Vec<Vec2> {
+            base.iter()
+                .zip(delta)
+                .map(|(&base, &delta)| base + delta * scale)
+                .collect()
+        };
+
+        let positions: Vec<Vec2> = planets.iter().map(|planet| planet.position).collect();
+        let velocities = mask(planets.iter().map(|planet| planet.velocity).collect());
+
+        let k1_position = velocities.clone();
+        let k1_velocity = mask(acceleration_field(&positions, planets, self));
+        let k2_position = offset(&velocities, &k1_velocity, dt / 2.0);
+        let k2_velocity = mask(acceleration_field(
+            &offset(&positions, &k1_position, dt / 2.0),
+            planets,
+            self,
+        ));
+        let k3_position = offset(&velocities, &k2_velocity, dt / 2.0);
+        let k3_velocity = mask(acceleration_field(
+            &offset(&positions, &k2_position, dt / 2.0),
+            planets,
+            self,
+        ));
+        let k4_position = offset(&velocities, &k3_velocity, dt);
+        let k4_velocity = mask(acceleration_field(
+            &offset(&positions, &k3_position, dt),
+            planets,
+            self,
+        ));
+
+        (0..planets.len())
+            .map(|i| {
+                (
+                    positions[i]
+                        + (k1_position[i]
+                            + 2.0 * k2_position[i]
+                            + 2.0 * k3_position[i]
+                            + k4_position[i])
+                            * dt
+                            / 6.0,
+                    velocities[i]
+                        + (k1_velocity[i]
+                            + 2.0 * k2_velocity[i]
+                            + 2.0 * k3_velocity[i]
+                            + k4_velocity[i])
+                            * dt
+                            / 6.0,
+                )
+            })
+            .collect()
+    }
+
+    fn candidate_pairs(&self, planets: &[Planet], previous: &[Vec2]) -> Vec<(usize, usize)> {
+        let bounds: Vec<Rect> = planets
+            .iter()
+            .zip(previous)
+            .map(|(planet, &previous)| {
+                let start = planet.position - self.separation(previous, planet.position);
+                let min = planet.position.min(start) - Vec2::splat(planet.radius);
+                let max = planet.position.max(start) + Vec2::splat(planet.radius);
+                Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+            })
+            .collect();
+
+        if self.boundary != Boundary::Wrap {
+            return SpatialHash::new(&bounds, self.cell_size).pairs();
+        }
+
+        let size = vec2(self.bounds.w, self.bounds.h);
+
+        if bounds
+            .iter()
+            .any(|rect| rect.w * 2.0 >= size.x || rect.h * 2.0 >= size.y)
+        {
+            return (0..planets.len())
+                .flat_map(|i| ((i + 1)..planets.len()).map(move |j| (i, j)))
+                .collect();
+        }
+
+        // Wrap each box into the world and add shifted copies where it crosses an edge, so
+        // bodies touching across the seam still share a cell.
+        let mut owners = Vec::new();
+        let mut images = Vec::new();
+
+        for (index, rect) in bounds.iter().enumerate() {
+            let x = self.bounds.x + (rect.x - self.bounds.x).rem_euclid(size.x);
+            let y = self.bounds.y + (rect.y - self.bounds.y).rem_euclid(size.y);
+
+            for (dx, dy) in [
+                (0.0, 0.0),
+                (-size.x, 0.0),
+                (0.0, -size.y),
+                (-size.x, -size.y),
+            ] {
+                let image = Rect::new(x + dx, y + dy, rect.w, rect.h);
+
+                if image.overlaps(&self.bounds) {
+                    owners.push(index);
+                    images.push(image);
+                }
+            }
+        }
+
+        let mut pairs: Vec<(usize, usize)> = SpatialHash::new(&images, self.cell_size)
+            .pairs()
+            .into_iter()
+            .map(|(a, b)| (owners[a].min(owners[b]), owners[a].max(owners[b])))
+            .filter(|(i, j)| i != j)
+            .collect();
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+
+    fn sweep_collisions(
+        &self,
+        planets: &mut [Planet],
+        previous: &[Vec2],
+        pairs: &[(usize, usize)],
+    ) -> Vec<((usize, usize), CollisionEvent)> {
+        let mut impacts: Vec<Option<f32>> = vec![None; planets.len()];
+        let mut events = Vec::new();
+
+        for &(i, j) in pairs {
+            if planets[i].is_test_particle() && planets[j].is_test_particle() {
+                continue;
+            }
+
+            let start = self.separation(previous[i], previous[j]);
+            let end = self.separation(planets[i].position, planets[j].position);
+            let radius = (planets[i].radius + planets[j].radius) * CONTACT_TOLERANCE;
+
+            if let Some(time) = time_of_impact(start, end - start, radius) {
+                for k in [i, j] {
+                    impacts[k] = Some(impacts[k].map_or(time, |other| other.min(time)));
+                }
+
+                if !self.merge_on_collision {
+                    events.push((
+                        (i, j),
+                        CollisionEvent {
+                            time: self.time + self.dt * time,
+                            first: planets[i].display_name(i),
+                            second: planets[j].display_name(j),
+                            speed: (planets[i].velocity - planets[j].velocity).length(),
+                            outcome: CollisionOutcome::Bounce,
+                        },
+                    ));
+                }
+            }
+        }
+
+        for (i, impact) in impacts.into_iter().enumerate() {
+            if let Some(time) = impact {
+                let planet = &mut planets[i];
+                let motion = self.separation(previous[i], planet.position);
+
+                planet.position -= motion * (1.0 - time);
+            }
+        }
+
+        events
+    }
+
+    fn fragment_planets(
+        &self,
+        planets: &mut Vec<Planet>,
+        pairs: &[(usize, usize)],
+    ) -> Vec<((usize, usize), CollisionEvent)> {
+        let count = self.fragment_count.max(2);
+        let mut shattered = vec![false; planets.len()];
+        let mut events = Vec::new();
+
+        for &(i, j) in pairs {
+            if shattered[i] || shattered[j] || planets.len() + count - 1 > MAX_BODIES {
+                continue;
+            }
+
+            let (a, b) = (&planets[i], &planets[j]);
+            let direction = self.separation(a.position, b.position);
+            let relative_velocity = b.velocity - a.velocity;
+
+            if a.is_test_particle()
+                || b.is_test_particle()
+                || a.is_fixed()
+                || b.is_fixed()
+                || !a.overlaps(b, self)
+                || direction.dot(relative_velocity) >= 0.0
+                || relative_velocity.length() < self.fragment_speed
+            {
+                continue;
+            }
+
+            let index = if a.mass >= b.mass { i } else { j };
+            let planet = &planets[index];
+            let radius = planet.radius / (count as f32).cbrt();
+
+            if radius < MIN_FRAGMENT_RADIUS {
+                continue;
+            }
+
+            let first = a.display_name(i);
+            let second = b.display_name(j);
+            let speed = relative_velocity.length();
+            let total_mass = a.mass + b.mass;
+            let center_velocity = (a.velocity * a.mass + b.velocity * b.mass) / total_mass;
+            // The energy of the impact in the pair's rest frame is all the debris gets.
+            let impact_energy = 0.5 * a.mass * b.mass / total_mass * speed * speed;
+            let burst = (2.0 * impact_energy / planet.mass).sqrt();
+            let spacing = radius / (std::f32::consts::PI / count as f32).sin() * 1.05;
+
+            let fragments: Vec<Planet> = (0..count)
+                .map(|k| {
+                    let outward = Vec2::from_angle(k as f32 / count as f32 * std::f32::consts::TAU);
+                    let mut fragment = Planet::new(
+                        planet.position + outward * spacing,
+                        radius,
+                        center_velocity + outward * burst,
+                        planet.mass / count as f32,
+                        planet.color,
+                    );
+                    fragment.acceleration = planet.acceleration;
+                    fragment
+                })
+                .collect();
+
+            let other = if index == i { j } else { i };
+            planets[other].velocity = center_velocity;
+
+            let mut fragments = fragments.into_iter();
+            planets[index] = fragments.next().expect("at least two fragments");
+            planets.extend(fragments);
+            shattered[i] = true;
+            shattered[j] = true;
+
+            events.push((
+                (i, j),
+                CollisionEvent {
+                    time: self.time + self.dt,
+                    first,
+                    second,
+                    speed,
+                    outcome: CollisionOutcome::Fragment(count),
+                },
+            ));
+        }
+
+        events
+    }
+
+    fn apply_boundary(&self, planet: &mut Planet) {
+        match self.boundary {
+            Boundary::None => {}
+            Boundary::Walls => planet.bounce_off_walls(self.bounds, self.restitution),
+            Boundary::Wrap => planet.wrap_around(self.bounds),
+        }
+    }
+
+    pub fn separation(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let direction = to - from;
+
+        if self.boundary != Boundary::Wrap {
+            return direction;
+        }
+
+        let size = vec2(self.bounds.w, self.bounds.h);
+        direction - (direction / size).round() * size
+    }
+
+    // A logarithmic halo potential, 0.5 * v^2 * ln(r^2 + r_c^2), whose rotation curve
+    // rises through the core radius and flattens out at `halo_strength`.
+    pub fn halo_acceleration(&self, position: Vec2) -> Vec2 {
+        if !self.halo {
+            return Vec2::ZERO;
+        }
+
+        let offset = self.separation(self.halo_center, position);
+        let denominator =
+            (offset.length_squared() + self.halo_scale * self.halo_scale).max(f32::EPSILON);
+
+        -offset * self.halo_strength * self.halo_strength / denominator
+    }
+
+    pub fn halo_circular_speed(&self, position: Vec2) -> f32 {
+        if !self.halo {
+            return 0.0;
+        }
+
+        let distance_squared = self.separation(self.halo_center, position).length_squared();
+        let denominator = (distance_squared + self.halo_scale * self.halo_scale).max(f32::EPSILON);
+
+        self.halo_strength * (distance_squared / denominator).sqrt()
+    }
+
+    pub fn halo_energy(&self) -> f32 {
+        if !self.halo {
+            return 0.0;
+        }
+
+        self.planets
+            .iter()
+            .map(|planet| {
+                let distance_squared = self
+                    .separation(self.halo_center, planet.position)
+                    .length_squared();
+                let potential = 0.5
+                    * self.halo_strength
+                    * self.halo_strength
+                    * (distance_squared + self.halo_scale * self.halo_scale)
+                        .max(f32::EPSILON)
+                        .ln();
+
+                planet.mass * potential
+            })
+            .sum()
+    }
+
+    // Speeds up generated orbits so they stay circular once the halo's pull is added.
+    pub fn add_halo_rotation(&self, planets: &mut [Planet]) {
+        for planet in planets.iter_mut().filter(|planet| !planet.is_fixed()) {
+            let speed = planet.velocity.length();
+
+            if speed > 0.0 {
+                let halo_speed = self.halo_circular_speed(planet.position);
+                planet.velocity *= (speed * speed + halo_speed * halo_speed).sqrt() / speed;
+            }
+        }
+    }
+
+    pub fn field_at(&self, position: Vec2) -> Vec2 {
+        acceleration_at(position, &self.planets, self)
+    }
+
+    pub fn predict_trajectory(&self, planet: &Planet) -> Vec<Vec2> {
+        let mut bodies: Vec<Planet> = self
+            .planets
+            .iter()
+            .chain(std::iter::once(planet))
+            .map(Planet::without_history)
+            .collect();
+        let mut path = Vec::with_capacity(PREDICTION_STEPS);
+
+        for _ in 0..PREDICTION_STEPS {
+            for body in &mut bodies {
+                body.drift(DT);
+                self.apply_boundary(body);
+            }
+
+            let accelerations: Vec<Vec2> = bodies
+                .iter()
+                .map(|body| acceleration_at(body.position, &bodies, self))
+                .collect();
+
+            for (body, acceleration) in bodies.iter_mut().zip(accelerations) {
+                body.kick(acceleration, DT);
+            }
+
+            path.push(bodies[bodies.len() - 1].position);
+        }
+
+        path
+    }
+}
+
+fn time_of_impact(start: Vec2, motion: Vec2, radius: f32) -> Option<f32> {
+    let a = motion.length_squared();
+    let b = 2.0 * start.dot(motion);
+    let c = start.length_squared() - radius * radius;
+
+    if c <= 0.0 || a <= f32::EPSILON {
+        return None;
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+
+    (0.0..=1.0).contains(&time).then_some(time)
+}
+
+fn merge_planets(
+    planets: &mut Vec<Planet>,
+    pairs: &[(usize, usize)],
+    simulation: &Simulation,
+) -> (Vec<CollisionEvent>, Option<Vec<Option<usize>>>) {
+    let mut merges: Vec<(usize, usize)> = Vec::new();
+    let mut merged = vec![false; planets.len()];
+
+    for &(i, j) in pairs {
+        let both_test_particles = planets[i].is_test_particle() && planets[j].is_test_particle();
+
+        if !merged[i]
+            && !merged[j]
+            && !both_test_particles
+            && planets[i].overlaps(&planets[j], simulation)
+        {
+            merges.push((i, j));
+            merged[j] = true;
+        }
+    }
+
+    let mut events = Vec::new();
+
+    for &(i, j) in &merges {
+        let (head, tail) = planets.split_at_mut(j);
+        let mut other = tail[0].without_history();
+        other.position = head[i].position + simulation.separation(head[i].position, other.position);
+
+        let first = head[i].display_name(i);
+        let speed = (head[i].velocity - other.velocity).length();
+        head[i].absorb(&other);
+
+        events.push(CollisionEvent {
+            time: simulation.time + simulation.dt,
+            first,
+            second: other.display_name(j),
+            speed,
+            outcome: CollisionOutcome::Merge(head[i].display_name(i)),
+        });
+    }
+
+    if merges.is_empty() {
+        return (events, None);
+    }
+
+    let mut absorber: Vec<usize> = (0..planets.len()).collect();
+
+    for &(i, j) in &merges {
+        absorber[j] = i;
+    }
+
+    let kept: Vec<bool> = merged.iter().map(|&merged| !merged).collect();
+    let retained = retain_indexed(planets, &kept);
+    let remap = absorber.iter().map(|&index| retained[index]).collect();
+
+    (events, Some(remap))
+}
+
+fn retain_indexed(planets: &mut Vec<Planet>, keep: &[bool]) -> Vec<Option<usize>> {
+    let mut kept = 0;
+    let remap = keep
+        .iter()
+        .map(|&keep| {
+            kept += keep as usize;
+            keep.then(|| kept - 1)
+        })
+        .collect();
+
+    let mut index = 0;
+    planets.retain(|_| {
+        index += 1;
+        keep[index - 1]
+    });
+
+    remap
+}
+
+fn recover_non_finite(
+    planets: &mut Vec<Planet>,
+    remap: &mut Option<Vec<Option<usize>>>,
+) -> (usize, usize) {
+    let count = planets.len();
+    let keep: Vec<bool> = planets
+        .iter()
+        .map(|planet| planet.position.is_finite())
+        .collect();
+
+    if keep.contains(&false) {
+        let retained = retain_indexed(planets, &keep);
+
+        *remap = Some(match remap.take() {
+            Some(earlier) => earlier
+                .into_iter()
+                .map(|index| index.and_then(|index| retained[index]))
+                .collect(),
+            None => retained,
+        });
+    }
+
+    let mut reset = 0;
+
+    for planet in planets.iter_mut() {
+        if !planet.velocity.is_finite() || !planet.acceleration.is_finite() {
+            planet.velocity = Vec2::ZERO;
+            planet.acceleration = Vec2::ZERO;
+            reset += 1;
+        }
+    }
+
+    (count - planets.len(), reset)
+}
+
+pub fn circular_orbit_velocity(around: &Planet, position: Vec2, g: f32) -> Vec2 {
+    let radius = position - around.position;
+    let distance = radius.length();
+
+    if distance <= f32::EPSILON || g * around.mass <= 0.0 {
+        return Vec2::ZERO;
+    }
+
+    radius.perp() / distance * (g * around.mass / distance).sqrt()
+}
+
+pub fn total_energy(planets: &[Planet], g: f32, softening: f32) -> f32 {
+    let mut energy = 0.0;
+
+    for (i, planet) in planets.iter().enumerate() {
+        energy += 0.5 * planet.mass * planet.velocity.length_squared();
+
+        for other_planet in &planets[i + 1..] {
+            let distance = (planet.position.distance_squared(other_planet.position)
+                + softening * softening)
+                .sqrt();
+
+            if distance > 0.0 {
+                energy -= g * planet.mass * other_planet.mass / distance;
+            }
+        }
+    }
+
+    energy
+}
+
+pub struct OrbitalElements {
+    pub semi_major_axis: f32,
+    pub eccentricity: f32,
+    pub period: Option<f32>,
+    pub periapsis: Vec2,
+    pub apoapsis: Option<Vec2>,
+}
+
+pub fn dominant_body(planets: &[Planet], index: usize) -> Option<usize> {
+    planets
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .max_by(|(_, a), (_, b)| a.mass.total_cmp(&b.mass))
+        .map(|(i, _)| i)
+}
+
+pub fn hill_radius(simulation: &Simulation, index: usize) -> Option<f32> {
+    let planets = &simulation.planets;
+    let planet = &planets[index];
+    let primary = &planets[dominant_body(planets, index)?];
+    let distance = simulation
+        .separation(primary.position, planet.position)
+        .length();
+
+    (primary.mass > planet.mass).then(|| distance * (planet.mass / (3.0 * primary.mass)).cbrt())
+}
+
+// Puts `moon` on a circular orbit about `parent` on top of the parent's own motion. The
+// two-body speed is split by mass so the pair's barycenter keeps the parent's velocity.
+pub fn add_moon(
+    simulation: &mut Simulation,
+    parent: usize,
+    mut moon: Planet,
+) -> Result<usize, String> {
+    let g = simulation.g;
+    let hill = hill_radius(simulation, parent);
+    let host = &simulation.planets[parent];
+    let name = host.display_name(parent);
+    let offset = simulation.separation(host.position, moon.position);
+    let distance = offset.length();
+
+    if g <= 0.0 || host.mass <= 0.0 {
+        return Err(format!("{} has no attracting mass to orbit", name));
+    }
+
+    if distance <= host.radius + moon.radius {
+        return Err(format!(
+            "a moon at distance {:.0} would touch {}",
+            distance, name
+        ));
+    }
+
+    if let Some(hill) = hill.filter(|&hill| distance > hill) {
+        return Err(format!(
+            "{:.0} is beyond {}'s Hill radius of {:.0}, so the moon would not stay bound",
+            distance, name, hill
+        ));
+    }
+
+    let total = host.mass + moon.mass.max(0.0);
+    let relative =
+        circular_orbit_velocity(host, host.position + offset, g) * (total / host.mass).sqrt();
+    moon.velocity = host.velocity + relative * host.mass / total;
+    simulation.planets[parent].velocity -= relative * moon.mass.max(0.0) / total;
+    simulation.planets.push(moon);
+
+    Ok(simulation.planets.len() - 1)
+}
+
+pub fn orbital_elements(planet: &Planet, primary: &Planet, g: f32) -> Option<OrbitalElements> {
+    let mu = g * (primary.mass + planet.mass);
+    let position = planet.position - primary.position;
+    let velocity = planet.velocity - primary.velocity;
+    let distance = position.length();
+
+    if mu <= 0.0 || distance <= f32::EPSILON {
+        return None;
+    }
+
+    let energy = velocity.length_squared() / 2.0 - mu / distance;
+    let eccentricity_vector = ((velocity.length_squared() - mu / distance) * position
+        - position.dot(velocity) * velocity)
+        / mu;
+    let eccentricity = eccentricity_vector.length();
+    let semi_major_axis = -mu / (2.0 * energy);
+    let period =
+        (energy < 0.0).then(|| std::f32::consts::TAU * (semi_major_axis.powi(3) / mu).sqrt());
+
+    let axis = if eccentricity > f32::EPSILON {
+        eccentricity_vector / eccentricity
+    } else {
+        position / distance
+    };
+    let periapsis = primary.position + axis * semi_major_axis * (1.0 - eccentricity);
+    let apoapsis =
+        (energy < 0.0).then(|| primary.position - axis * semi_major_axis * (1.0 + eccentricity));
+
+    Some(OrbitalElements {
+        semi_major_axis,
+        eccentricity,
+        period,
+        periapsis,
+        apoapsis,
+    })
+}
+
+pub fn time_to_impact(a: &Planet, b: &Planet) -> Option<f32> {
+    let position = b.position - a.position;
+    let velocity = b.velocity - a.velocity;
+    let radius = a.radius + b.radius;
+
+    let approach = position.dot(velocity);
+    let speed_squared = velocity.length_squared();
+    let gap = position.length_squared() - radius * radius;
+
+    if gap <= 0.0 || approach >= 0.0 || speed_squared <= 0.0 {
+        return None;
+    }
+
+    let discriminant = approach * approach - speed_squared * gap;
+
+    (discriminant >= 0.0).then(|| (-approach - discriminant.sqrt()) / speed_squared)
+}
+
+pub fn predicted_impacts(planets: &[Planet], horizon: f32) -> Vec<(usize, usize, f32)> {
+    let mut impacts = Vec::new();
+
+    for (i, a) in planets.iter().enumerate() {
+        for (j, b) in planets.iter().enumerate().skip(i + 1) {
+            if let Some(time) = time_to_impact(a, b).filter(|&time| time <= horizon) {
+                impacts.push((i, j, time));
+            }
+        }
+    }
+
+    impacts
+}
+
+pub fn total_momentum(planets: &[Planet]) -> Vec2 {
+    planets.iter().fold(Vec2::ZERO, |sum, planet| {
+        sum + planet.velocity * planet.mass
+    })
+}
+
+pub fn angular_momentum(planets: &[Planet]) -> f32 {
+    let center = center_of_mass(planets);
+
+    planets
+        .iter()
+        .map(|planet| planet.mass * (planet.position - center).perp_dot(planet.velocity))
+        .sum()
+}
+
+pub fn unbound_planets(planets: &[Planet], g: f32, softening: f32) -> Vec<bool> {
+    let total_mass: f32 = planets.iter().map(|planet| planet.mass).sum();
+    let total_momentum = total_momentum(planets);
+
+    planets
+        .iter()
+        .enumerate()
+        .map(|(i, planet)| {
+            let rest_mass = total_mass - planet.mass;
+
+            if rest_mass <= 0.0 {
+                return false;
+            }
+
+            let rest_velocity = (total_momentum - planet.velocity * planet.mass) / rest_mass;
+            let potential: f32 = planets
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, other)| {
+                    g * other.mass
+                        / (planet.position.distance_squared(other.position) + softening * softening)
+                            .sqrt()
+                })
+                .sum();
+
+            0.5 * (planet.velocity - rest_velocity).length_squared() > potential
+        })
+        .collect()
+}
+
+pub fn center_of_mass(planets: &[Planet]) -> Vec2 {
+    let mass: f32 = planets.iter().map(|planet| planet.mass).sum();
+
+    if mass > 0.0 {
+        planets.iter().fold(Vec2::ZERO, |sum, planet| {
+            sum + planet.position * planet.mass
+        }) / mass
+    } else if !planets.is_empty() {
+        planets
+            .iter()
+            .fold(Vec2::ZERO, |sum, planet| sum + planet.position)
+            / planets.len() as f32
+    } else {
+        Vec2::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets;
+
+    fn body(position: Vec2, velocity: Vec2, mass: f32) -> Planet {
+        PlanetBuilder::new()
+            .position(position)
+            .velocity(velocity)
+            .mass(mass)
+            .radius(5.0)
+            .build()
+    }
+
+    fn three_body() -> Simulation {
+        Simulation::new(vec![
+            body(vec2(0.0, 0.0), vec2(0.0, -10.0), 100.0),
+            body(vec2(200.0, 0.0), vec2(0.0, 30.0), 20.0),
+            body(vec2(-150.0, 80.0), vec2(15.0, 0.0), 40.0),
+        ])
+    }
+
+    #[test]
+    fn momentum_is_conserved_without_merging() {
+        let mut simulation = three_body();
+        let initial = total_momentum(&simulation.planets);
+
+        for _ in 0..10_000 {
+            simulation.step();
+        }
+
+        let drift = total_momentum(&simulation.planets) - initial;
+        assert!(drift.length() < 1e-2, "momentum drifted by {}", drift);
+    }
+
+    #[test]
+    fn impact_is_predicted_for_approaching_planets() {
+        let planets = vec![
+            Planet::new(vec2(0.0, 0.0), 5.0, vec2(10.0, 0.0), 1.0, WHITE),
+            Planet::new(vec2(100.0, 0.0), 5.0, vec2(-10.0, 0.0), 1.0, WHITE),
+            Planet::new(vec2(0.0, 100.0), 5.0, vec2(0.0, 10.0), 1.0, WHITE),
+        ];
+
+        let impacts = predicted_impacts(&planets, 10.0);
+        assert_eq!(impacts.len(), 1);
+
+        let (i, j, time) = impacts[0];
+        assert_eq!((i, j), (0, 1));
+        assert!((time - 4.5).abs() < 1e-4, "predicted impact at {}", time);
+        assert!(predicted_impacts(&planets, 4.0).is_empty());
+    }
+
+    fn bullet() -> Simulation {
+        let mut simulation = Simulation::new(vec![
+            PlanetBuilder::new().mass(100.0).build(),
+            PlanetBuilder::new()
+                .position(vec2(-100.0, 0.0))
+                .velocity(vec2(20_000.0, 0.0))
+                .radius(1.0)
+                .mass(1.0)
+                .build(),
+        ]);
+        simulation.g = 0.0;
+        simulation
+    }
+
+    #[test]
+    fn wrapped_broadphase_finds_pairs_across_the_seam() {
+        let mut planets = presets::spawn_random(300, 4, BOUNDS);
+        planets.push(body(vec2(2.0, 300.0), Vec2::ZERO, 1.0));
+        planets.push(body(vec2(797.0, 300.0), Vec2::ZERO, 1.0));
+        let mut simulation = Simulation::new(planets);
+        simulation.boundary = Boundary::Wrap;
+        let planets = &simulation.planets;
+        let positions: Vec<Vec2> = planets.iter().map(|planet| planet.position).collect();
+        let overlapping = |&(i, j): &(usize, usize)| planets[i].overlaps(&planets[j], &simulation);
+
+        let brute_force: Vec<(usize, usize)> = (0..planets.len())
+            .flat_map(|i| ((i + 1)..planets.len()).map(move |j| (i, j)))
+            .filter(overlapping)
+            .collect();
+        let hashed: Vec<(usize, usize)> = simulation
+            .candidate_pairs(planets, &positions)
+            .into_iter()
+            .filter(overlapping)
+            .collect();
+
+        assert!(brute_force
+            .iter()
+            .any(|&(i, j)| { (positions[i] - positions[j]).length() > BOUNDS.w / 2.0 }));
+        assert_eq!(brute_force, hashed);
+    }
+
+    #[test]
+    fn fast_planet_does_not_tunnel_through() {
+        let mut simulation = bullet();
+        simulation.step();
+
+        assert!(simulation.planets[1].velocity.x < 20_000.0);
+        assert!(simulation.planets[1].position.x < 0.0);
+    }
+
+    #[test]
+    fn fast_planet_merges_on_impact() {
+        let mut simulation = bullet();
+        simulation.merge_on_collision = true;
+        let report = simulation.step();
+
+        assert_eq!(simulation.planets.len(), 1);
+        assert_eq!(report.collisions.len(), 1);
+        assert!(matches!(
+            report.collisions[0].outcome,
+            CollisionOutcome::Merge(_)
+        ));
+        assert!(simulation.step().is_empty());
+    }
+
+    #[test]
+    fn elastic_head_on_collision_swaps_velocities() {
+        let mut simulation = Simulation::new(vec![
+            body(vec2(-4.9, 0.0), vec2(10.0, 0.0), 4.0),
+            body(vec2(4.9, 0.0), vec2(-10.0, 0.0), 4.0),
+        ]);
+        simulation.g = 0.0;
+        simulation.restitution = 1.0;
+
+        let kinetic = |simulation: &Simulation| -> f32 {
+            simulation
+                .planets
+                .iter()
+                .map(|planet| 0.5 * planet.mass * planet.velocity.length_squared())
+                .sum()
+        };
+        let initial = kinetic(&simulation);
+        simulation.step();
+
+        assert_eq!(simulation.planets[0].velocity, vec2(-10.0, 0.0));
+        assert_eq!(simulation.planets[1].velocity, vec2(10.0, 0.0));
+        assert_eq!(kinetic(&simulation), initial);
+
+        let gap = simulation.planets[0]
+            .position
+            .distance(simulation.planets[1].position);
+        assert!(
+            gap >= 10.0 - 1e-4,
+            "bodies still overlap at distance {}",
+            gap
+        );
+    }
+
+    #[test]
+    fn fragments_do_not_gain_kinetic_energy() {
+        let mut simulation = Simulation::new(vec![
+            body(vec2(-4.9, 0.0), vec2(40.0, 0.0), 6.0),
+            body(vec2(4.9, 0.0), vec2(-20.0, 0.0), 2.0),
+        ]);
+        simulation.g = 0.0;
+        simulation.fragmentation = true;
+        simulation.fragment_speed = 1.0;
+
+        let kinetic = |planets: &[Planet]| -> f32 {
+            planets
+                .iter()
+                .map(|planet| 0.5 * planet.mass * planet.velocity.length_squared())
+                .sum()
+        };
+        let before = kinetic(&simulation.planets);
+        let report = simulation.step();
+        let after = kinetic(&simulation.planets);
+
+        assert!(simulation.planets.len() > 2);
+        assert!(
+            after <= before * (1.0 + 1e-4),
+            "{} before, {} after",
+            before,
+            after
+        );
+        assert_eq!(report.collisions.len(), 1);
+        assert!(matches!(
+            report.collisions[0].outcome,
+            CollisionOutcome::Fragment(_)
+        ));
+    }
+
+    #[test]
+    fn restitution_scales_relative_speed() {
+        for restitution in [0.0, 0.3, 0.75] {
+            let mut simulation = Simulation::new(vec![
+                body(vec2(-4.9, 0.0), vec2(10.0, 0.0), 2.0),
+                body(vec2(4.9, 0.0), vec2(-5.0, 0.0), 6.0),
+            ]);
+            simulation.g = 0.0;
+            simulation.restitution = restitution;
+
+            let relative = |simulation: &Simulation| {
+                (simulation.planets[1].velocity - simulation.planets[0].velocity).x
+            };
+            let before = relative(&simulation);
+            simulation.step();
+            let after = relative(&simulation);
+
+            assert!(
+                (after + restitution * before).abs() < 1e-4,
+                "restitution {}: relative speed {} before, {} after",
+                restitution,
+                before,
+                after
+            );
+        }
+    }
+
+    #[test]
+    fn merges_report_where_planets_moved() {
+        let mut simulation = Simulation::new(vec![
+            body(vec2(-200.0, 0.0), Vec2::ZERO, 1.0),
+            body(vec2(0.0, 0.0), Vec2::ZERO, 5.0),
+            body(vec2(300.0, 0.0), Vec2::ZERO, 1.0),
+            body(vec2(2.0, 0.0), Vec2::ZERO, 5.0),
+            body(vec2(500.0, 0.0), Vec2::ZERO, 1.0),
+        ]);
+        simulation.g = 0.0;
+        simulation.merge_on_collision = true;
+
+        let report = simulation.step();
+
+        assert_eq!(simulation.planets.len(), 4);
+        assert_eq!(
+            (0..5).map(|i| report.new_index(i)).collect::<Vec<_>>(),
+            vec![Some(0), Some(1), Some(2), Some(1), Some(3)]
+        );
+        assert_eq!(simulation.planets[3].position, vec2(500.0, 0.0));
+        assert!(simulation.step().remap.is_none());
+    }
+
+    #[test]
+    fn coincident_planets_stay_finite() {
+        for merge_on_collision in [false, true] {
+            let mut simulation = Simulation::new(vec![
+                body(vec2(50.0, 50.0), Vec2::ZERO, 10.0),
+                body(vec2(50.0, 50.0), vec2(1.0, 0.0), 10.0),
+            ]);
+            simulation.softening = 0.0;
+            simulation.merge_on_collision = merge_on_collision;
+
+            for _ in 0..10 {
+                let report = simulation.step();
+                assert_eq!(report.removed_non_finite + report.reset_non_finite, 0);
+            }
+
+            assert!(!simulation.planets.is_empty());
+            for planet in &simulation.planets {
+                assert!(planet.position.is_finite(), "position {}", planet.position);
+                assert!(planet.velocity.is_finite(), "velocity {}", planet.velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn head_on_drop_stays_below_escape_speed() {
+        let mass = 100.0;
+        let mut simulation = Simulation::new(vec![
+            body(vec2(-50.0, 0.0), Vec2::ZERO, mass),
+            body(vec2(50.0, 0.0), Vec2::ZERO, mass),
+        ]);
+        let escape = (2.0 * simulation.g * mass / simulation.planets[0].radius).sqrt();
+
+        for _ in 0..500 {
+            simulation.step();
+
+            for planet in &simulation.planets {
+                assert!(planet.position.is_finite(), "position {}", planet.position);
+                assert!(planet.velocity.is_finite(), "velocity {}", planet.velocity);
+                assert!(
+                    planet.velocity.length() < escape,
+                    "speed {} reached escape speed {}",
+                    planet.velocity.length(),
+                    escape
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crowded_collisions_conserve_momentum() {
+        let planets = presets::spawn_random(150, 2, Rect::new(0.0, 0.0, 150.0, 150.0));
+        let mut simulation = Simulation::new(planets);
+        simulation.g = 0.0;
+        simulation.restitution = 0.5;
+
+        let initial = total_momentum(&simulation.planets);
+        let scale: f32 = simulation
+            .planets
+            .iter()
+            .map(|planet| planet.mass * planet.velocity.length())
+            .sum();
+
+        for _ in 0..200 {
+            simulation.step();
+        }
+
+        let drift = total_momentum(&simulation.planets) - initial;
+        assert!(
+            drift.length() < scale * 1e-4,
+            "momentum drifted by {} against a scale of {}",
+            drift,
+            scale
+        );
+    }
+
+    #[test]
+    fn stepping_is_deterministic() {
+        let mut first = three_body();
+        let mut second = three_body();
+
+        for _ in 0..1_000 {
+            first.step();
+            second.step();
+        }
+
+        for (a, b) in first.planets.iter().zip(&second.planets) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.velocity, b.velocity);
+        }
+    }
+
+    fn distant_binary() -> Simulation {
+        let center = vec2(10_000.0, 10_000.0);
+        let speed = (G * 100.0 / 100.0).sqrt();
+
+        Simulation::new(vec![
+            Planet::new(
+                center - vec2(25.0, 0.0),
+                1.0,
+                vec2(0.0, -speed),
+                100.0,
+                WHITE,
+            ),
+            Planet::new(
+                center + vec2(25.0, 0.0),
+                1.0,
+                vec2(0.0, speed),
+                100.0,
+                WHITE,
+            ),
+        ])
+    }
+
+    fn energy_drift(mut simulation: Simulation) -> f32 {
+        let initial = total_energy(&simulation.planets, simulation.g, simulation.softening);
+
+        for _ in 0..20_000 {
+            simulation.step();
+        }
+
+        let energy = total_energy(&simulation.planets, simulation.g, simulation.softening);
+        ((energy - initial) / initial).abs()
+    }
+
+    #[test]
+    fn double_precision_reduces_energy_drift() {
+        let single = energy_drift(distant_binary());
+
+        let mut simulation = distant_binary();
+        simulation.double_precision = true;
+        let double = energy_drift(simulation);
+
+        assert!(
+            double < single / 10.0,
+            "f32 drift {}, f64 drift {}",
+            single,
+            double
+        );
+    }
+
+    fn kepler_error(integrator: Integrator) -> f32 {
+        let radius = 100.0;
+        let speed = (G * 1000.0 / radius).sqrt();
+        let mut sun = Planet::new(Vec2::ZERO, 10.0, Vec2::ZERO, 1000.0, WHITE);
+        sun.anchored = true;
+
+        let mut simulation = Simulation::new(vec![
+            sun,
+            Planet::new(vec2(radius, 0.0), 1.0, vec2(0.0, speed), 1.0, WHITE),
+        ]);
+        simulation.softening = 0.0;
+        simulation.integrator = integrator;
+
+        let steps = 600;
+        for _ in 0..steps {
+            simulation.step();
+        }
+
+        let angle = speed / radius * steps as f32 * DT;
+        let expected = Vec2::from_angle(angle) * radius;
+        simulation.planets[1].position.distance(expected)
+    }
+
+    #[test]
+    fn verlet_keeps_circular_orbit_radius_within_one_percent() {
+        let radius = 100.0;
+        let speed = (G * 1000.0 / radius).sqrt();
+        let mut sun = Planet::new(Vec2::ZERO, 10.0, Vec2::ZERO, 1000.0, WHITE);
+        sun.anchored = true;
+
+        let mut simulation = Simulation::new(vec![
+            sun,
+            Planet::new(vec2(radius, 0.0), 1.0, vec2(0.0, speed), 1.0, WHITE),
+        ]);
+        simulation.softening = 0.0;
+
+        for step in 0..10_000 {
+            simulation.step();
+
+            let distance = simulation.planets[1].position.length();
+            assert!(
+                (distance - radius).abs() < radius * 0.01,
+                "radius {} after {} steps",
+                distance,
+                step + 1
+            );
+        }
+    }
+
+    #[test]
+    fn rk4_tracks_kepler_orbit_better_than_euler() {
+        let euler = kepler_error(Integrator::Euler);
+        let rk4 = kepler_error(Integrator::Rk4);
+
+        assert!(
+            rk4 < euler / 100.0,
+            "RK4 error {}, Euler error {}",
+            rk4,
+            euler
+        );
+    }
+
+    #[test]
+    fn halo_keeps_circular_orbits_circular() {
+        for (integrator, name) in [(Integrator::Verlet, "Verlet"), (Integrator::Rk4, "RK4")] {
+            let mut simulation = Simulation::new(Vec::new());
+            simulation.g = 0.0;
+            simulation.halo = true;
+            simulation.halo_center = Vec2::ZERO;
+            simulation.integrator = integrator;
+            simulation.double_precision = integrator == Integrator::Verlet;
+
+            let position = vec2(500.0, 0.0);
+            let speed = simulation.halo_circular_speed(position);
+            simulation.planets = vec![body(position, vec2(0.0, speed), 1.0)];
+
+            for _ in 0..2000 {
+                simulation.step();
+            }
+
+            let radius = simulation.planets[0].position.length();
+            assert!(
+                (radius - 500.0).abs() < 1.0,
+                "{} orbit drifted to radius {}",
+                name,
+                radius
+            );
+        }
+    }
+
+    #[test]
+    fn drag_decays_velocity_exponentially() {
+        let mut simulation = Simulation::new(vec![body(Vec2::ZERO, vec2(100.0, 0.0), 1.0)]);
+        simulation.g = 0.0;
+        simulation.drag = 1.0;
+
+        for _ in 0..60 {
+            simulation.step();
+        }
+
+        let speed = simulation.planets[0].velocity.length();
+        let expected = 100.0 * (-simulation.drag * simulation.dt * 60.0).exp();
+        assert!(
+            (speed - expected).abs() < expected * 0.02,
+            "speed {} after one second, expected about {}",
+            speed,
+            expected
+        );
+    }
+
+    #[test]
+    fn moon_stays_bound_to_a_moving_parent() {
+        let star = body(Vec2::ZERO, Vec2::ZERO, 1000.0);
+        let position = vec2(300.0, 0.0);
+        let velocity = circular_orbit_velocity(&star, position, G);
+        let mut simulation = Simulation::new(vec![star, body(position, velocity, 20.0)]);
+        let parent = 1;
+        let Some(hill) = hill_radius(&simulation, parent) else {
+            panic!("the planet should have a Hill radius");
+        };
+        let moon = PlanetBuilder::new()
+            .position(simulation.planets[parent].position + vec2(hill / 2.0, 0.0))
+            .radius(1.0)
+            .mass(0.01)
+            .build();
+        let index = add_moon(&mut simulation, parent, moon).unwrap();
+        let barycenter = total_momentum(&simulation.planets[parent..]) / (20.0 + 0.01);
+        assert!((barycenter - velocity).length() < 1e-3);
+
+        for _ in 0..600 {
+            simulation.step();
+            let distance = simulation.planets[index]
+                .position
+                .distance(simulation.planets[parent].position);
+            assert!(distance < hill, "moon escaped to {} of {}", distance, hill);
+        }
+
+        let far = body(vec2(1000.0, 0.0), Vec2::ZERO, 0.01);
+        assert!(add_moon(&mut simulation, parent, far).is_err());
+    }
+
+    #[test]
+    fn moons_measure_distance_across_the_wrap_seam() {
+        let mut simulation = Simulation::new(vec![
+            body(vec2(15.0, 100.0), Vec2::ZERO, 1000.0),
+            body(vec2(195.0, 100.0), Vec2::ZERO, 200.0),
+        ]);
+        simulation.boundary = Boundary::Wrap;
+        simulation.bounds = Rect::new(0.0, 0.0, 200.0, 200.0);
+
+        let Some(hill) = hill_radius(&simulation, 1) else {
+            panic!("the planet should have a Hill radius");
+        };
+        assert!((hill - 20.0 * (200.0f32 / 3000.0).cbrt()).abs() < 1e-3);
+
+        // Across the seam the moon is 7 units from its parent, not 193.
+        let moon = PlanetBuilder::new()
+            .position(vec2(2.0, 100.0))
+            .radius(1.0)
+            .mass(0.01)
+            .build();
+        let index = add_moon(&mut simulation, 1, moon).unwrap();
+        assert!(simulation.planets[index].velocity.y.abs() > 0.0);
+    }
+}
@@ -0,0 +1,84 @@
+use macroquad::audio::{load_sound_from_bytes, play_sound, PlaySoundParams, Sound};
+
+use crate::rng::Rng;
+use crate::sim::StepReport;
+
+const SAMPLE_RATE: u32 = 44100;
+const IMPACT_DURATION: f32 = 0.15;
+const LOUD_SPEED: f32 = 500.0;
+
+pub struct Sounds {
+    impact: Option<Sound>,
+}
+
+impl Sounds {
+    pub async fn load() -> Self {
+        let impact = match load_sound_from_bytes(&impact_wav()).await {
+            Ok(sound) => Some(sound),
+            Err(error) => {
+                eprintln!("Failed to load impact sound: {}", error);
+                None
+            }
+        };
+
+        Sounds { impact }
+    }
+
+    pub fn play(&self, report: &StepReport, volume: f32) {
+        let Some(impact) = self.impact else {
+            return;
+        };
+
+        let loudest = report
+            .collisions
+            .iter()
+            .map(|event| event.speed)
+            .fold(0.0, f32::max);
+
+        if loudest > 0.0 && volume > 0.0 {
+            play_sound(
+                impact,
+                PlaySoundParams {
+                    looped: false,
+                    volume: volume * (loudest / LOUD_SPEED).min(1.0),
+                },
+            );
+        }
+    }
+}
+
+fn impact_wav() -> Vec<u8> {
+    let mut rng = Rng::new(1);
+    let count = (SAMPLE_RATE as f32 * IMPACT_DURATION) as u32;
+    let samples: Vec<i16> = (0..count)
+        .map(|i| {
+            let time = i as f32 / SAMPLE_RATE as f32;
+            let envelope = (-time * 30.0).exp();
+            let thud = (time * std::f32::consts::TAU * (90.0 + 60.0 * envelope)).sin();
+            let crack = rng.range(-1.0, 1.0) * (-time * 80.0).exp();
+
+            ((thud * 0.7 + crack * 0.3) * envelope * i16::MAX as f32) as i16
+        })
+        .collect();
+
+    let data_size = count * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    wav
+}
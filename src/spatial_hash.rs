@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+const MAX_CELLS_PER_BODY: f32 = 1024.0;
+
+pub struct SpatialHash {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    oversized: Vec<usize>,
+    count: usize,
+}
+
+impl SpatialHash {
+    pub fn new(bounds: &[Rect], cell_size: f32) -> Self {
+        let mut hash = SpatialHash {
+            cells: HashMap::new(),
+            oversized: Vec::new(),
+            count: bounds.len(),
+        };
+
+        for (index, rect) in bounds.iter().enumerate() {
+            let min = (rect.point() / cell_size).floor();
+            let max = ((rect.point() + rect.size()) / cell_size).floor();
+            let span = max - min + Vec2::ONE;
+
+            if !min.is_finite() || !max.is_finite() || span.x * span.y > MAX_CELLS_PER_BODY {
+                hash.oversized.push(index);
+                continue;
+            }
+
+            for x in min.x as i32..=max.x as i32 {
+                for y in min.y as i32..=max.y as i32 {
+                    hash.cells.entry((x, y)).or_default().push(index);
+                }
+            }
+        }
+
+        hash
+    }
+
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+
+        for bodies in self.cells.values() {
+            for (n, &i) in bodies.iter().enumerate() {
+                for &j in &bodies[n + 1..] {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+
+        for &i in &self.oversized {
+            for j in (0..self.count).filter(|&j| j != i) {
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn pairs_match_brute_force() {
+        let mut rng = Rng::new(3);
+        let circles: Vec<(Vec2, f32)> = (0..300)
+            .map(|_| {
+                let position = vec2(rng.range(-200.0, 200.0), rng.range(-200.0, 200.0));
+                (position, rng.range(1.0, 12.0))
+            })
+            .collect();
+        let bounds: Vec<Rect> = circles
+            .iter()
+            .map(|&(position, radius)| {
+                Rect::new(
+                    position.x - radius,
+                    position.y - radius,
+                    radius * 2.0,
+                    radius * 2.0,
+                )
+            })
+            .collect();
+        let overlapping = |&(i, j): &(usize, usize)| {
+            circles[i].0.distance(circles[j].0) <= circles[i].1 + circles[j].1
+        };
+
+        let brute_force: Vec<(usize, usize)> = (0..circles.len())
+            .flat_map(|i| ((i + 1)..circles.len()).map(move |j| (i, j)))
+            .filter(overlapping)
+            .collect();
+        let hashed: Vec<(usize, usize)> = SpatialHash::new(&bounds, 16.0)
+            .pairs()
+            .into_iter()
+            .filter(overlapping)
+            .collect();
+
+        assert!(!brute_force.is_empty());
+        assert_eq!(brute_force, hashed);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+use crate::sim::Planet;
+
+const MAX_DEPTH: usize = 50;
+
+pub struct UndoStack {
+    undo: VecDeque<Vec<Planet>>,
+    redo: Vec<Vec<Planet>>,
+}
+
+impl UndoStack {
+    pub fn new() -> Self {
+        UndoStack {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Vec<Planet>) {
+        if self.undo.len() == MAX_DEPTH {
+            self.undo.pop_front();
+        }
+
+        self.undo.push_back(snapshot);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, planets: &mut Vec<Planet>) -> bool {
+        let Some(snapshot) = self.undo.pop_back() else {
+            return false;
+        };
+
+        self.redo.push(snapshot_of(planets));
+        *planets = snapshot;
+        true
+    }
+
+    pub fn redo(&mut self, planets: &mut Vec<Planet>) -> bool {
+        let Some(snapshot) = self.redo.pop() else {
+            return false;
+        };
+
+        self.undo.push_back(snapshot_of(planets));
+        *planets = snapshot;
+        true
+    }
+}
+
+pub fn snapshot_of(planets: &[Planet]) -> Vec<Planet> {
+    planets.iter().map(Planet::without_history).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::prelude::*;
+
+    fn planets(count: usize) -> Vec<Planet> {
+        (0..count)
+            .map(|i| Planet::new(vec2(i as f32, 0.0), 1.0, Vec2::ZERO, 1.0, WHITE))
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_restore_snapshots() {
+        let mut stack = UndoStack::new();
+        let mut current = planets(1);
+
+        for count in 2..MAX_DEPTH + 10 {
+            stack.push(snapshot_of(&current));
+            current = planets(count);
+        }
+
+        let latest = current.len();
+        let mut undone = 0;
+        while stack.undo(&mut current) {
+            undone += 1;
+        }
+
+        assert_eq!(undone, MAX_DEPTH);
+        assert_eq!(current.len(), latest - MAX_DEPTH);
+
+        while stack.redo(&mut current) {}
+        assert_eq!(current.len(), latest);
+    }
+}